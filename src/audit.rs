@@ -0,0 +1,467 @@
+use std::{
+    collections::VecDeque,
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc::{self, error::TrySendError},
+};
+use tracing::{info, warn};
+use crate::error::BridgeError;
+use crate::registry::CallContext;
+
+/// Une ligne du journal d'audit : un appel d'outil et ce qu'il a exécuté.
+#[derive(Serialize, Clone)]
+pub struct AuditRecord {
+    /// RFC 3339, UTC.
+    pub timestamp: String,
+    pub session_id: Option<String>,
+    /// `token:<nom>` ou `oauth:<sub>` ; `None` sans authentification.
+    pub principal: Option<String>,
+    pub tool: String,
+    pub arguments: Value,
+    /// SQL exécuté par l'outil (après validation), `None` s'il n'en a exécuté aucun.
+    pub sql: Option<String>,
+    pub duration_ms: u64,
+    /// Lignes renvoyées dans la page ; `None` si aucune requête n'a abouti.
+    pub row_count: Option<usize>,
+    /// Lignes restantes laissées à un `next_cursor`, ou cellules coupées par le budget.
+    pub truncated: bool,
+    pub error: Option<String>,
+}
+
+/// Ce qu'une page de requête a exécuté pendant un appel d'outil, pour son `AuditRecord`.
+#[derive(Debug, Default)]
+pub struct QueryTrace {
+    state: Mutex<TracedQuery>,
+}
+
+#[derive(Debug, Default)]
+struct TracedQuery {
+    sql: Option<String>,
+    row_count: Option<usize>,
+    truncated: bool,
+}
+
+impl QueryTrace {
+    /// Enregistré avant l'exécution, pour que les requêtes refusées soient aussi journalisées.
+    pub fn started(&self, sql: &str) {
+        *self.state.lock().unwrap() = TracedQuery { sql: Some(sql.into()), ..TracedQuery::default() };
+    }
+
+    pub fn finished(&self, row_count: usize, truncated: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.row_count = Some(row_count);
+        state.truncated = truncated;
+    }
+}
+
+/// Destinations des lignes, écrites dans l'ordre par une tâche de fond.
+struct Sinks {
+    file: Option<File>,
+    database: Option<(PgPool, String)>,
+}
+
+/// Journal d'audit des appels d'outils.
+///
+/// Chaque ligne part vers les destinations de `MCP_PG_AUDIT_FILE` (JSON lines,
+/// ouvert en ajout) et `MCP_PG_AUDIT_DATABASE_URL` (une table écrite par sa
+/// propre connexion, le pool des requêtes étant en lecture seule). Les
+/// `MCP_PG_AUDIT_HISTORY` dernières restent aussi en mémoire pour `query_history`.
+///
+/// La file vers la tâche d'écriture est bornée (`MCP_PG_AUDIT_QUEUE`) : si les
+/// destinations ne suivent pas, les lignes en trop sont perdues et comptées, plutôt
+/// que d'accumuler de la mémoire sans limite.
+pub struct AuditLog {
+    sender: Option<mpsc::Sender<AuditRecord>>,
+    /// Lignes perdues depuis le dernier avertissement de la tâche d'écriture.
+    dropped: Arc<AtomicU64>,
+    recent: Mutex<VecDeque<AuditRecord>>,
+    capacity: usize,
+    /// `MCP_PG_AUDIT_ARGUMENTS=names` : seuls les noms des arguments sont gardés.
+    argument_values: bool,
+}
+
+impl AuditLog {
+    pub async fn from_env(prefix: &str) -> Result<Self, BridgeError> {
+        let var = |name: &str| env::var(format!("{prefix}_{name}")).ok().filter(|v| !v.is_empty());
+        let capacity = var("AUDIT_HISTORY").and_then(|v| v.parse().ok()).unwrap_or(200);
+        let queue = var("AUDIT_QUEUE").and_then(|v| v.parse().ok()).unwrap_or(1024).max(1);
+        let argument_values = match var("AUDIT_ARGUMENTS").as_deref() {
+            None | Some("full") => true,
+            Some("names") => false,
+            Some(other) => {
+                return Err(BridgeError::Api(format!(
+                    "Invalid {prefix}_AUDIT_ARGUMENTS: {other} (expected full or names)"
+                )));
+            }
+        };
+
+        let file = match var("AUDIT_FILE") {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .map_err(|e| BridgeError::Api(format!("Cannot open audit file {path}: {e}")))?;
+                info!(path = %path, "Audit log written to file");
+                Some(file)
+            }
+            None => None,
+        };
+        let database = match var("AUDIT_DATABASE_URL") {
+            Some(url) => {
+                let table = var("AUDIT_TABLE").unwrap_or_else(|| "mcp_audit_log".into());
+                if !table.split('.').all(is_identifier) {
+                    return Err(BridgeError::Api(format!("Invalid {prefix}_AUDIT_TABLE: {table}")));
+                }
+                let pool = PgPoolOptions::new().max_connections(1).connect(&url).await?;
+                create_table(&pool, &table).await?;
+                info!(table = %table, "Audit log written to PostgreSQL");
+                Some((pool, table))
+            }
+            None => None,
+        };
+
+        let dropped = Arc::new(AtomicU64::new(0));
+        let sender = match (file, database) {
+            (None, None) => None,
+            (file, database) => {
+                let (sender, receiver) = mpsc::channel(queue);
+                tokio::spawn(write_loop(Sinks { file, database }, receiver, dropped.clone()));
+                Some(sender)
+            }
+        };
+        Ok(Self { sender, dropped, recent: Mutex::default(), capacity, argument_values })
+    }
+
+    /// Commence l'audit d'un appel d'outil de `ctx` : la garde renvoyée l'enregistre
+    /// à la fin, ou comme abandonné si l'appel n'aboutit jamais (client parti).
+    pub fn start<'a>(&'a self, ctx: &'a CallContext, tool: &'a str, arguments: &'a Value) -> AuditGuard<'a> {
+        AuditGuard { audit: self, ctx, tool, arguments, started: Instant::now(), finished: false }
+    }
+
+    /// Enregistre un appel d'outil de `ctx`, avec ce qu'a capturé sa `QueryTrace`.
+    fn record(
+        &self,
+        ctx: &CallContext,
+        tool: &str,
+        arguments: &Value,
+        duration: Duration,
+        error: Option<String>,
+    ) {
+        let mut record = AuditRecord {
+            timestamp: DateTime::<Utc>::from(SystemTime::now())
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            session_id: ctx.session_id.clone(),
+            principal: ctx.principal_name().map(String::from),
+            tool: tool.into(),
+            arguments: if self.argument_values {
+                arguments.clone()
+            } else {
+                names_only(arguments)
+            },
+            sql: None,
+            duration_ms: duration.as_millis() as u64,
+            row_count: None,
+            truncated: false,
+            error,
+        };
+        if let Some(trace) = &ctx.query {
+            let traced = std::mem::take(&mut *trace.state.lock().unwrap());
+            record.sql = traced.sql;
+            record.row_count = traced.row_count;
+            record.truncated = traced.truncated;
+        }
+
+        if self.capacity > 0 {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == self.capacity {
+                recent.pop_front();
+            }
+            recent.push_back(record.clone());
+        }
+        if let Some(sender) = &self.sender
+            && let Err(TrySendError::Full(record)) = sender.try_send(record)
+            && self.dropped.fetch_add(1, Ordering::Relaxed) == 0
+        {
+            // Un avertissement par rafale : la tâche d'écriture donne le total ensuite.
+            warn!(tool = %record.tool, "Audit queue full, dropping records");
+        }
+    }
+
+    /// Les dernières lignes de la session et du principal de `ctx`, les plus récentes
+    /// d'abord, sans les appels à `query_history` eux-mêmes. Rien pour un appelant
+    /// HTTP sans état ni jeton : ils se ressemblent tous.
+    pub fn history(&self, ctx: &CallContext, limit: usize, errors_only: bool) -> Vec<AuditRecord> {
+        if ctx.anonymous_request.is_some() {
+            return Vec::new();
+        }
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|r| {
+                r.session_id == ctx.session_id
+                    && r.principal.as_deref() == ctx.principal_name()
+                    && r.tool != "query_history"
+                    && (!errors_only || r.error.is_some())
+            })
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Appel d'outil en cours d'audit, enregistré même si la requête est abandonnée.
+pub struct AuditGuard<'a> {
+    audit: &'a AuditLog,
+    ctx: &'a CallContext,
+    tool: &'a str,
+    arguments: &'a Value,
+    started: Instant,
+    finished: bool,
+}
+
+impl AuditGuard<'_> {
+    pub fn finish(mut self, error: Option<String>) {
+        self.finished = true;
+        self.audit.record(self.ctx, self.tool, self.arguments, self.started.elapsed(), error);
+    }
+}
+
+impl Drop for AuditGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let error = Some("Request dropped before completion".into());
+            self.audit.record(self.ctx, self.tool, self.arguments, self.started.elapsed(), error);
+        }
+    }
+}
+
+/// Les arguments d'un appel sans leurs valeurs, qui peuvent contenir des données
+/// (paramètres liés, filtres) : `{"query": null, "params": null}`.
+fn names_only(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(map) => map.keys().map(|k| (k.clone(), Value::Null)).collect(),
+        _ => Value::Null,
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Seulement si elle manque : `CREATE TABLE IF NOT EXISTS` exige `CREATE` sur le
+/// schéma même si la table existe, ce qu'un rôle limité à l'insertion n'a pas.
+async fn create_table(pool: &PgPool, table: &str) -> Result<(), BridgeError> {
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    if exists {
+        return Ok(());
+    }
+    sqlx::query(&format!(
+        "CREATE TABLE {table} (\
+             id bigserial PRIMARY KEY, \
+             at timestamptz NOT NULL, \
+             session_id text, \
+             principal text, \
+             tool text NOT NULL, \
+             arguments jsonb NOT NULL, \
+             sql text, \
+             duration_ms bigint NOT NULL, \
+             row_count integer, \
+             truncated boolean NOT NULL, \
+             error text)"
+    ))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Écrit les lignes à mesure qu'elles arrivent. Une destination en échec ne fait
+/// qu'avertir : l'appel a déjà eu lieu, et l'autre destination l'a peut-être.
+async fn write_loop(
+    mut sinks: Sinks,
+    mut receiver: mpsc::Receiver<AuditRecord>,
+    dropped: Arc<AtomicU64>,
+) {
+    while let Some(record) = receiver.recv().await {
+        let lost = dropped.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            warn!(dropped = lost, "Audit records were dropped while the queue was full");
+        }
+        if let Some(file) = &mut sinks.file {
+            let mut line = serde_json::to_string(&record).unwrap_or_default();
+            line.push('\n');
+            let written = match file.write_all(line.as_bytes()).await {
+                Ok(()) => file.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                warn!(error = %e, "Could not write to the audit file");
+            }
+        }
+        if let Some((pool, table)) = &sinks.database {
+            let inserted = sqlx::query(&format!(
+                "INSERT INTO {table} \
+                 (at, session_id, principal, tool, arguments, sql, duration_ms, row_count, truncated, error) \
+                 VALUES ($1::timestamptz, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+            ))
+            .bind(&record.timestamp)
+            .bind(&record.session_id)
+            .bind(&record.principal)
+            .bind(&record.tool)
+            .bind(&record.arguments)
+            .bind(&record.sql)
+            .bind(record.duration_ms as i64)
+            .bind(record.row_count.map(|n| n as i32))
+            .bind(record.truncated)
+            .bind(&record.error)
+            .execute(pool)
+            .await;
+            if let Err(e) = inserted {
+                warn!(error = %e, tool = %record.tool, "Could not insert the audit record");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Principal;
+    use serde_json::json;
+
+    fn audit_log(capacity: usize) -> AuditLog {
+        AuditLog {
+            sender: None,
+            dropped: Arc::default(),
+            recent: Mutex::default(),
+            capacity,
+            argument_values: true,
+        }
+    }
+
+    fn caller(session: Option<&str>, sub: Option<&str>) -> CallContext {
+        CallContext {
+            session_id: session.map(String::from),
+            principal: sub
+                .map(|sub| Arc::new(Principal::from_claims(json!({ "sub": sub }), Vec::new()))),
+            ..CallContext::default()
+        }
+    }
+
+    fn call(audit: &AuditLog, ctx: &CallContext, tool: &str, error: Option<&str>) {
+        let arguments = json!({ "query": tool });
+        audit.start(ctx, tool, &arguments).finish(error.map(String::from));
+    }
+
+    fn tools(records: &[AuditRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.tool.as_str()).collect()
+    }
+
+    #[test]
+    fn keeps_each_caller_to_its_own_history() {
+        let audit = audit_log(10);
+        let alice = caller(Some("s1"), Some("alice"));
+        call(&audit, &alice, "list_tables", None);
+        call(&audit, &caller(Some("s1"), Some("bob")), "bob_tool", None);
+        call(&audit, &caller(Some("s2"), Some("alice")), "other_session", None);
+        call(&audit, &caller(None, Some("alice")), "stateless", None);
+        call(&audit, &alice, "sql_read_query", Some("syntax error"));
+        call(&audit, &alice, "query_history", None);
+        call(&audit, &alice, "describe_table", None);
+
+        // Les plus récents d'abord, sans `query_history`.
+        let history = audit.history(&alice, 20, false);
+        assert_eq!(tools(&history), ["describe_table", "sql_read_query", "list_tables"]);
+        assert_eq!(tools(&audit.history(&alice, 2, false)), ["describe_table", "sql_read_query"]);
+        let errors = audit.history(&alice, 20, true);
+        assert_eq!(tools(&errors), ["sql_read_query"]);
+        assert_eq!(errors[0].error.as_deref(), Some("syntax error"));
+
+        // Un jeton sans session ne voit que ses appels sans session.
+        assert_eq!(tools(&audit.history(&caller(None, Some("alice")), 20, false)), ["stateless"]);
+        let bob = caller(Some("s1"), Some("bob"));
+        assert_eq!(tools(&audit.history(&bob, 20, false)), ["bob_tool"]);
+        assert!(audit.history(&caller(Some("s1"), None), 20, false).is_empty());
+    }
+
+    #[test]
+    fn gives_anonymous_stateless_callers_no_history() {
+        let audit = audit_log(10);
+        let anonymous = CallContext {
+            anonymous_request: Some("request".into()),
+            ..CallContext::default()
+        };
+        call(&audit, &anonymous, "list_tables", None);
+        call(&audit, &caller(None, None), "describe_table", None);
+
+        assert!(audit.history(&anonymous, 20, false).is_empty());
+        // Les deux appels sont gardés, mais rien ne distingue leurs appelants.
+        assert_eq!(audit.recent.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn keeps_the_last_records_only() {
+        let audit = audit_log(2);
+        let ctx = caller(Some("s1"), None);
+        for tool in ["first", "second", "third"] {
+            call(&audit, &ctx, tool, None);
+        }
+        assert_eq!(tools(&audit.history(&ctx, 20, false)), ["third", "second"]);
+
+        let audit = audit_log(0);
+        call(&audit, &ctx, "first", None);
+        assert!(audit.history(&ctx, 20, false).is_empty());
+    }
+
+    #[test]
+    fn records_dropped_calls() {
+        let audit = audit_log(10);
+        let ctx = caller(Some("s1"), None);
+        drop(audit.start(&ctx, "sql_read_query", &json!({})));
+        let history = audit.history(&ctx, 20, false);
+        assert_eq!(history[0].error.as_deref(), Some("Request dropped before completion"));
+    }
+
+    #[test]
+    fn drops_and_counts_records_when_the_queue_is_full() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let audit = AuditLog { sender: Some(sender), ..audit_log(0) };
+        let ctx = caller(Some("s1"), None);
+        for tool in ["first", "second", "third"] {
+            call(&audit, &ctx, tool, None);
+        }
+        assert_eq!(audit.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(receiver.try_recv().unwrap().tool, "first");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn keeps_argument_names_only_when_asked() {
+        let audit = AuditLog { argument_values: false, ..audit_log(10) };
+        let ctx = caller(Some("s1"), None);
+        let arguments = json!({ "query": "SELECT $1", "params": ["secret"] });
+        audit.start(&ctx, "sql_read_query", &arguments).finish(None);
+
+        let history = audit.history(&ctx, 20, false);
+        assert_eq!(history[0].arguments, json!({ "query": null, "params": null }));
+    }
+}
@@ -0,0 +1,368 @@
+use std::{collections::HashSet, fs, path::Path, sync::Arc};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+use crate::error::BridgeError;
+use crate::oauth::{scoped, AuthError, OAuth};
+use crate::registry::ToolRegistry;
+use crate::state::AppState;
+
+/// Fichier des jetons d'accès (`MCP_PG_AUTH_FILE`).
+#[derive(Deserialize)]
+struct AuthFile {
+    #[serde(default)]
+    tokens: Vec<TokenEntry>,
+}
+
+/// Une entrée `[[tokens]]`. Sans `tools` ou `tables`, tout est autorisé.
+#[derive(Deserialize)]
+struct TokenEntry {
+    name: String,
+    token: String,
+    tools: Option<Vec<String>>,
+    tables: Option<Vec<String>>,
+    role: Option<String>,
+}
+
+/// Qui appelle et ce qu'il peut utiliser ; `None` signifie sans restriction.
+#[derive(Debug)]
+pub struct Principal {
+    /// `token:<nom>` pour un jeton du fichier, `oauth:<sub>` pour un jeton d'accès
+    /// OAuth : un jeton statique ne peut pas se faire passer pour un sujet OAuth.
+    pub name: String,
+    /// Scopes accordés par le jeton d'accès OAuth ; vide pour les jetons statiques.
+    pub scopes: Vec<String>,
+    /// Claims du jeton d'accès OAuth, pour les outils qui ont besoin de plus que `sub`.
+    pub claims: Option<Value>,
+    /// Rôle PostgreSQL des transactions d'outil de ce jeton (`SET LOCAL ROLE`).
+    pub role: Option<String>,
+    tools: Option<HashSet<String>>,
+    tables: Option<HashSet<String>>,
+}
+
+impl Principal {
+    /// Les scopes `tool:<nom>` et `table:<nom>` restreignent ce que le jeton peut utiliser.
+    pub fn from_claims(claims: Value, scopes: Vec<String>) -> Self {
+        Self {
+            name: format!("oauth:{}", claims.get("sub").and_then(Value::as_str).unwrap_or_default()),
+            tools: scoped(&scopes, "tool:"),
+            tables: scoped(&scopes, "table:").map(|t| t.iter().map(|t| qualified(t)).collect()),
+            scopes,
+            claims: Some(claims),
+            role: None,
+        }
+    }
+
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.tools.as_ref().is_none_or(|tools| tools.contains(tool))
+    }
+
+    /// `table` est `schema.table`, ou un nom seul pour le schéma `public`.
+    pub fn allows_table(&self, table: &str) -> bool {
+        self.tables
+            .as_ref()
+            .is_none_or(|tables| tables.contains(&qualified(table)))
+    }
+
+    pub fn restricts_tables(&self) -> bool {
+        self.tables.is_some()
+    }
+
+    /// Tables auxquelles le jeton est limité, triées ; `None` s'il lit tout.
+    pub fn table_scope(&self) -> Option<Vec<String>> {
+        self.tables.as_ref().map(|tables| {
+            let mut tables: Vec<String> = tables.iter().cloned().collect();
+            tables.sort();
+            tables
+        })
+    }
+}
+
+/// `public.quotes` pour `quotes`. Les noms sont comparés tels que stockés dans le
+/// catalogue, donc en minuscules sauf si la table a été créée avec un nom entre guillemets.
+pub(crate) fn qualified(table: &str) -> String {
+    match table.contains('.') {
+        true => table.into(),
+        false => format!("public.{table}"),
+    }
+}
+
+/// `quotes` pour `public.quotes` : les relations de `public` sont nommées sans
+/// schéma dans les listes et la description du schéma, les autres avec.
+pub(crate) fn unqualified(relation: &str) -> &str {
+    relation.strip_prefix("public.").unwrap_or(relation)
+}
+
+/// Jetons acceptés sur `/sse`, `/messages` et `/mcp` : jetons statiques de
+/// `MCP_PG_AUTH_FILE` et/ou jetons d'accès JWT d'un émetteur OAuth.
+pub struct Authenticator {
+    tokens: Vec<(String, Arc<Principal>)>,
+    oauth: Option<OAuth>,
+}
+
+impl Authenticator {
+    pub fn new(oauth: Option<OAuth>) -> Self {
+        Self { tokens: Vec::new(), oauth }
+    }
+
+    pub fn oauth(&self) -> Option<&OAuth> {
+        self.oauth.as_ref()
+    }
+
+    /// Charge le fichier des jetons. Un scope nommant un outil inconnu n'est qu'un
+    /// avertissement : l'outil peut venir d'un `MCP_PG_TOOLS_FILE` absent ici.
+    pub fn load_tokens(&mut self, path: &Path, tools: &ToolRegistry) -> Result<(), BridgeError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| BridgeError::Api(format!("Cannot read {}: {e}", path.display())))?;
+        self.parse_tokens(&content, &path.display().to_string(), tools)
+    }
+
+    fn parse_tokens(&mut self, content: &str, source: &str, tools: &ToolRegistry) -> Result<(), BridgeError> {
+        let file: AuthFile = toml::from_str(content)
+            .map_err(|e| BridgeError::Api(format!("Invalid auth file {source}: {e}")))?;
+
+        if file.tokens.is_empty() {
+            return Err(BridgeError::Api(format!("No token in {source}")));
+        }
+        let tokens = &mut self.tokens;
+        for entry in file.tokens {
+            if entry.token.len() < 16 {
+                return Err(BridgeError::Api(format!(
+                    "Token '{}': tokens must be at least 16 characters long",
+                    entry.name
+                )));
+            }
+            if tokens.iter().any(|(token, _)| *token == entry.token) {
+                return Err(BridgeError::Api(format!("Token '{}' is declared twice", entry.name)));
+            }
+            for tool in entry.tools.iter().flatten() {
+                if tools.get(tool).is_none() {
+                    warn!(token = %entry.name, tool = %tool, "Token scope names an unknown tool");
+                }
+            }
+            info!(token = %entry.name, "Registered bearer token");
+            let principal = Principal {
+                name: format!("token:{}", entry.name),
+                scopes: Vec::new(),
+                claims: None,
+                role: entry.role,
+                tools: entry.tools.map(|tools| tools.into_iter().collect()),
+                tables: entry
+                    .tables
+                    .map(|tables| tables.iter().map(|t| qualified(t)).collect()),
+            };
+            tokens.push((entry.token, Arc::new(principal)));
+        }
+        Ok(())
+    }
+
+    async fn authenticate(&self, token: Option<&str>) -> Result<Arc<Principal>, AuthError> {
+        let token = token.ok_or(AuthError::Missing)?;
+        // Chaque jeton est comparé en entier : la durée ne révèle aucun préfixe.
+        let known = self
+            .tokens
+            .iter()
+            .fold(None, |found, (candidate, principal)| {
+                match constant_time_eq(candidate.as_bytes(), token.as_bytes()) {
+                    true => Some(principal.clone()),
+                    false => found,
+                }
+            });
+        match (known, &self.oauth) {
+            (Some(principal), _) => Ok(principal),
+            (None, Some(oauth)) => oauth.validate(token).await.map(Arc::new),
+            (None, None) => Err(AuthError::InvalidToken("Unknown token".into())),
+        }
+    }
+
+    /// `401` / `403` avec le challenge RFC 6750, qui renvoie les clients OAuth
+    /// vers les métadonnées de la ressource protégée.
+    fn challenge(&self, error: AuthError) -> Response {
+        let mut params = Vec::new();
+        let status = match &error {
+            AuthError::Missing => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidToken(description) => {
+                params.push("error=\"invalid_token\"".to_string());
+                params.push(format!("error_description=\"{}\"", description.replace('"', "'")));
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::InsufficientScope(scope) => {
+                params.push("error=\"insufficient_scope\"".to_string());
+                params.push(format!("scope=\"{scope}\""));
+                StatusCode::FORBIDDEN
+            }
+        };
+        if let Some(oauth) = &self.oauth {
+            params.push(format!("resource_metadata=\"{}\"", oauth.metadata_url()));
+        }
+        let challenge = match params.is_empty() {
+            true => "Bearer".to_string(),
+            false => format!("Bearer {}", params.join(", ")),
+        };
+        let message = match error {
+            AuthError::Missing => "Missing bearer token".to_string(),
+            AuthError::InvalidToken(description) => format!("Invalid bearer token: {description}"),
+            AuthError::InsufficientScope(scope) => format!("Insufficient scope, required: {scope}"),
+        };
+        (status, [(header::WWW_AUTHENTICATE, challenge)], message).into_response()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Refuse les requêtes sans jeton valide par un `401` (`403` pour un jeton OAuth
+/// sans un scope requis) avant tout handler, et transmet le `Principal` aux
+/// handlers en extension.
+pub async fn require_auth(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let Some(auth) = &state.auth else {
+        return next.run(req).await;
+    };
+    match auth.authenticate(bearer_token(req.headers())).await {
+        Ok(principal) => {
+            debug!(principal = %principal.name, scopes = ?principal.scopes, "Authenticated request");
+            req.extensions_mut().insert(principal);
+            next.run(req).await
+        }
+        Err(error) => {
+            warn!(path = %req.uri().path(), error = ?error, "Rejected unauthenticated request");
+            auth.challenge(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    fn bearer(value: &[u8]) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_bytes(value).unwrap());
+        bearer_token(&headers).map(String::from)
+    }
+
+    fn authenticator(content: &str) -> Result<Authenticator, BridgeError> {
+        let mut auth = Authenticator::new(None);
+        auth.parse_tokens(content, "test", &ToolRegistry::default())?;
+        Ok(auth)
+    }
+
+    fn error(content: &str) -> String {
+        match authenticator(content) {
+            Ok(_) => panic!("accepted:\n{content}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn principal(tables: &[&str]) -> Principal {
+        let scopes = tables.iter().map(|t| format!("table:{t}")).collect();
+        Principal::from_claims(json!({ "sub": "alice" }), scopes)
+    }
+
+    #[test]
+    fn reads_the_bearer_token() {
+        assert_eq!(bearer(b"Bearer abc").as_deref(), Some("abc"));
+        assert_eq!(bearer(b"bearer abc").as_deref(), Some("abc"));
+        assert_eq!(bearer(b"BEARER  abc ").as_deref(), Some("abc"));
+        assert_eq!(bearer(b"Basic YWxhZGRpbjpvcGVu"), None);
+        assert_eq!(bearer(b"Bearer"), None);
+        assert_eq!(bearer(b"Bearer "), None);
+        assert_eq!(bearer(b"Bearer caf\xc3\xa9"), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn compares_in_constant_time() {
+        assert!(constant_time_eq(b"0123456789abcdef", b"0123456789abcdef"));
+        assert!(!constant_time_eq(b"0123456789abcdef", b"0123456789abcdeF"));
+        assert!(!constant_time_eq(b"0123456789abcdef", b"0123456789abcde"));
+        assert!(!constant_time_eq(b"", b"x"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn validates_the_token_file() {
+        assert!(error("").contains("No token in test"));
+        assert!(error("[[tokens]]\nname = \"a\"").contains("Invalid auth file test"));
+        assert!(error("[[tokens]]\nname = \"a\"\ntoken = \"short\"").contains("at least 16 characters"));
+        let twice = "[[tokens]]\nname = \"a\"\ntoken = \"0123456789abcdef\"\n\
+                     [[tokens]]\nname = \"b\"\ntoken = \"0123456789abcdef\"";
+        assert!(error(twice).contains("Token 'b' is declared twice"));
+        let missing = Authenticator::new(None).load_tokens(Path::new("/nonexistent/auth.toml"), &ToolRegistry::default());
+        assert!(missing.unwrap_err().to_string().contains("Cannot read /nonexistent/auth.toml"));
+    }
+
+    #[tokio::test]
+    async fn authenticates_the_declared_tokens() {
+        let auth = authenticator(
+            r#"
+            [[tokens]]
+            name = "reporting"
+            token = "0123456789abcdef"
+            tools = ["sql_read_query"]
+            tables = ["quotes", "market.prices"]
+            role = "reporting"
+            "#,
+        )
+        .unwrap();
+        let principal = auth.authenticate(Some("0123456789abcdef")).await.unwrap();
+        assert_eq!(principal.name, "token:reporting");
+        assert_eq!(principal.role.as_deref(), Some("reporting"));
+        assert!(principal.allows_tool("sql_read_query"));
+        assert!(!principal.allows_tool("list_tables"));
+        assert_eq!(principal.table_scope().unwrap(), ["market.prices", "public.quotes"]);
+
+        assert!(matches!(auth.authenticate(None).await, Err(AuthError::Missing)));
+        assert!(matches!(
+            auth.authenticate(Some("0123456789abcdeF")).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+        assert!(matches!(auth.authenticate(Some("0123456789abcde")).await, Err(AuthError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn matches_tables_with_their_schema() {
+        let scoped = principal(&["quotes", "market.prices"]);
+        assert!(scoped.restricts_tables());
+        assert!(scoped.allows_table("quotes"));
+        assert!(scoped.allows_table("public.quotes"));
+        assert!(scoped.allows_table("market.prices"));
+        assert!(!scoped.allows_table("prices"));
+        assert!(!scoped.allows_table("public.prices"));
+        assert!(!scoped.allows_table("market.quotes"));
+        // Comparés tels que dans le catalogue : `Quotes` serait une autre table.
+        assert!(!scoped.allows_table("Quotes"));
+
+        let qualified_only = principal(&["public.quotes"]);
+        assert!(qualified_only.allows_table("quotes"));
+
+        let unrestricted = principal(&[]);
+        assert!(!unrestricted.restricts_tables());
+        assert!(unrestricted.allows_table("anything.at_all"));
+        assert_eq!(unrestricted.table_scope(), None);
+    }
+
+    #[test]
+    fn qualifies_bare_names_with_public() {
+        assert_eq!(qualified("quotes"), "public.quotes");
+        assert_eq!(unqualified("public.quotes"), "quotes");
+        assert_eq!(unqualified("market.prices"), "market.prices");
+        assert_eq!(qualified("market.prices"), "market.prices");
+    }
+}
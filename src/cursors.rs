@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use serde_json::Value;
+use sqlx::{pool::PoolConnection, Executor, Postgres};
+use tracing::warn;
+use uuid::Uuid;
+use crate::handlers::format::OutputFormat;
+use crate::handlers::masking::Mask;
+use crate::registry::CallContext;
+
+/// Résultat conservé pour les pages suivantes : un curseur PostgreSQL
+/// `SCROLL ... WITH HOLD`, matérialisé au `COMMIT` de la première page, sur une
+/// connexion retirée du pool jusqu'à sa fermeture. Les pages suivantes lisent
+/// cet instantané : les lignes modifiées entre deux pages ne décalent rien.
+pub struct HeldResult {
+    /// Nom du curseur SQL, généré par le pont.
+    pub name: String,
+    pub sql: String,
+    pub format: OutputFormat,
+    /// Masques et colonnes de `structuredContent` calculés à la première page.
+    pub masks: Vec<(String, Mask)>,
+    pub columns: Option<Vec<Value>>,
+    pub owner: CursorOwner,
+    /// `None` une fois le curseur fermé.
+    pub connection: tokio::sync::Mutex<Option<PoolConnection<Postgres>>>,
+}
+
+impl HeldResult {
+    /// Nom de curseur SQL neuf, sûr à insérer tel quel dans `DECLARE` / `FETCH`.
+    pub fn cursor_name() -> String {
+        format!("mcp_{}", Uuid::new_v4().simple())
+    }
+
+    /// Ferme le curseur et rend sa connexion au pool, en tâche de fond.
+    fn release(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let Some(mut connection) = self.connection.lock().await.take() else { return };
+            let close = format!("CLOSE {}", self.name);
+            if let Err(e) = (&mut *connection).execute(sqlx::raw_sql(&close)).await {
+                warn!(cursor = %self.name, error = %e, "Could not close cursor, dropping its connection");
+                connection.close_on_drop();
+            }
+        });
+    }
+}
+
+/// Position dans un résultat conservé : l'offset de la page suivante.
+#[derive(Clone)]
+pub struct Cursor {
+    pub result: Arc<HeldResult>,
+    pub offset: usize,
+    expires_at: Instant,
+}
+
+/// Appelant auquel un curseur est réservé.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CursorOwner {
+    pub session_id: Option<String>,
+    /// Nom du jeton qui a exécuté la requête, si l'authentification est active.
+    pub principal: Option<String>,
+    /// Scope `table:` de ce jeton, trié : un jeton dont le scope a changé ne
+    /// poursuit plus les pages lues sous l'ancien.
+    pub tables: Option<Vec<String>>,
+}
+
+impl CursorOwner {
+    pub fn of(ctx: &CallContext) -> Self {
+        Self {
+            session_id: ctx.session_id.clone(),
+            principal: ctx.principal_name().map(String::from),
+            tables: ctx.principal.as_ref().and_then(|p| p.table_scope()),
+        }
+    }
+}
+
+/// Curseurs renvoyés en `next_cursor`, indexés par un jeton aléatoire opaque.
+///
+/// Un curseur n'est accepté que pour le `CursorOwner` qui l'a créé et expire
+/// `ttl` après le service de sa page. Chaque page a un nouveau jeton : un agent
+/// peut rejouer une page avec le même jeton tant qu'il n'a pas expiré.
+///
+/// Chaque résultat conservé occupe une connexion du pool : au plus `max_held`
+/// à la fois, les plus anciens étant fermés pour faire de la place. Un résultat
+/// est fermé après sa dernière page, à l'expiration de tous ses jetons, ou à la
+/// fin de la session qui l'a ouvert.
+pub struct CursorStore {
+    cursors: Mutex<Cursors>,
+    ttl: Duration,
+    max_held: usize,
+}
+
+#[derive(Default)]
+struct Cursors {
+    pages: HashMap<String, Cursor>,
+    /// Résultats conservés par nom de curseur, avec l'expiration de leur dernier jeton.
+    held: HashMap<String, (Arc<HeldResult>, Instant)>,
+}
+
+impl Cursors {
+    /// Retire les résultats choisis par `closed` et leurs jetons.
+    fn close_where(&mut self, closed: impl Fn(&HeldResult, Instant) -> bool) {
+        let names: Vec<String> = self
+            .held
+            .iter()
+            .filter(|(_, (result, expires_at))| closed(result, *expires_at))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            if let Some((result, _)) = self.held.remove(&name) {
+                result.release();
+            }
+        }
+        let held = &self.held;
+        self.pages.retain(|_, c| held.contains_key(&c.result.name));
+    }
+}
+
+impl CursorStore {
+    pub fn new(ttl: Duration, max_held: usize) -> Self {
+        Self { cursors: Mutex::default(), ttl, max_held }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Conserve un résultat dont la première page vient d'être servie et renvoie
+    /// le jeton de la page suivante, à `offset`.
+    pub fn hold(&self, result: Arc<HeldResult>, offset: usize) -> String {
+        let now = Instant::now();
+        let mut cursors = self.cursors.lock().unwrap();
+        cursors.pages.retain(|_, c| c.expires_at > now);
+        cursors.close_where(|_, expires_at| expires_at <= now);
+        while cursors.held.len() >= self.max_held.max(1) {
+            let Some(oldest) = cursors
+                .held
+                .values()
+                .min_by_key(|(_, expires_at)| *expires_at)
+                .map(|(result, _)| result.name.clone())
+            else {
+                break;
+            };
+            warn!(cursor = %oldest, max = self.max_held, "Too many held results, closing the oldest");
+            cursors.close_where(|result, _| result.name == oldest);
+        }
+        cursors.held.insert(result.name.clone(), (result.clone(), now + self.ttl));
+        Self::insert_page(&mut cursors, result, offset, now + self.ttl)
+    }
+
+    /// Jeton de la page à `offset` d'un résultat déjà conservé.
+    pub fn next(&self, result: &Arc<HeldResult>, offset: usize) -> String {
+        let expires_at = Instant::now() + self.ttl;
+        let mut cursors = self.cursors.lock().unwrap();
+        if let Some((_, held_until)) = cursors.held.get_mut(&result.name) {
+            *held_until = expires_at;
+        }
+        Self::insert_page(&mut cursors, result.clone(), offset, expires_at)
+    }
+
+    fn insert_page(cursors: &mut Cursors, result: Arc<HeldResult>, offset: usize, expires_at: Instant) -> String {
+        let token = Uuid::new_v4().to_string();
+        cursors.pages.insert(token.clone(), Cursor { result, offset, expires_at });
+        token
+    }
+
+    /// `None` si le jeton est inconnu, expiré ou appartient à un autre appelant.
+    pub fn get(&self, token: &str, owner: &CursorOwner) -> Option<Cursor> {
+        let cursors = self.cursors.lock().unwrap();
+        cursors
+            .pages
+            .get(token)
+            .filter(|c| c.expires_at > Instant::now() && c.result.owner == *owner)
+            .cloned()
+    }
+
+    /// Ferme un résultat : sa dernière page a été servie, ou sa connexion a échoué.
+    pub fn close(&self, result: &HeldResult) {
+        self.cursors.lock().unwrap().close_where(|held, _| held.name == result.name);
+    }
+
+    /// Ferme les résultats d'une session terminée.
+    pub fn close_session(&self, session_id: &str) {
+        self.cursors
+            .lock()
+            .unwrap()
+            .close_where(|result, _| result.owner.session_id.as_deref() == Some(session_id));
+    }
+
+    /// Ferme les résultats dont tous les jetons ont expiré.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        let mut cursors = self.cursors.lock().unwrap();
+        cursors.pages.retain(|_, c| c.expires_at > now);
+        cursors.close_where(|_, expires_at| expires_at <= now);
+    }
+
+    /// Nombre de résultats conservés, donc de connexions retirées du pool.
+    #[cfg(test)]
+    fn held(&self) -> usize {
+        self.cursors.lock().unwrap().held.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> CursorStore {
+        CursorStore::new(Duration::from_secs(60), 2)
+    }
+
+    fn owner(session_id: Option<&str>, principal: Option<&str>, tables: Option<&[&str]>) -> CursorOwner {
+        CursorOwner {
+            session_id: session_id.map(String::from),
+            principal: principal.map(String::from),
+            tables: tables.map(|t| t.iter().map(|t| t.to_string()).collect()),
+        }
+    }
+
+    fn result(owner: CursorOwner) -> Arc<HeldResult> {
+        Arc::new(HeldResult {
+            name: HeldResult::cursor_name(),
+            sql: "SELECT 1".into(),
+            format: OutputFormat::Csv,
+            masks: Vec::new(),
+            columns: None,
+            owner,
+            connection: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    #[tokio::test]
+    async fn returns_the_stored_position() {
+        let store = store();
+        let alice = owner(Some("s1"), Some("alice"), None);
+        let token = store.hold(result(alice.clone()), 500);
+        let cursor = store.get(&token, &alice).unwrap();
+        assert_eq!(cursor.result.sql, "SELECT 1");
+        assert_eq!(cursor.offset, 500);
+        assert_eq!(cursor.result.format, OutputFormat::Csv);
+        // Une page peut être rejouée avec le même jeton.
+        assert!(store.get(&token, &alice).is_some());
+    }
+
+    #[tokio::test]
+    async fn is_bound_to_its_session_and_principal() {
+        let store = store();
+        let token = store.hold(result(owner(Some("s1"), Some("alice"), None)), 500);
+        assert!(store.get(&token, &owner(Some("s2"), Some("alice"), None)).is_none());
+        assert!(store.get(&token, &owner(None, Some("alice"), None)).is_none());
+        assert!(store.get(&token, &owner(Some("s1"), Some("bob"), None)).is_none());
+        assert!(store.get(&token, &owner(Some("s1"), None, None)).is_none());
+        assert!(store.get("unknown", &owner(Some("s1"), Some("alice"), None)).is_none());
+    }
+
+    #[tokio::test]
+    async fn is_bound_to_its_table_scope() {
+        let store = store();
+        let scoped = owner(None, Some("alice"), Some(&["public.quotes"]));
+        let token = store.hold(result(scoped.clone()), 500);
+        assert!(store.get(&token, &scoped).is_some());
+        assert!(store.get(&token, &owner(None, Some("alice"), None)).is_none());
+        assert!(store.get(&token, &owner(None, Some("alice"), Some(&["public.assets"]))).is_none());
+        assert!(store.get(&token, &owner(None, Some("alice"), Some(&["public.assets", "public.quotes"]))).is_none());
+    }
+
+    #[tokio::test]
+    async fn expires() {
+        let store = CursorStore::new(Duration::ZERO, 2);
+        let token = store.hold(result(CursorOwner::default()), 500);
+        assert!(store.get(&token, &CursorOwner::default()).is_none());
+        store.sweep();
+        assert_eq!(store.held(), 0);
+    }
+
+    #[tokio::test]
+    async fn hands_out_a_new_token_per_page() {
+        let store = store();
+        let anonymous = CursorOwner::default();
+        let held = result(anonymous.clone());
+        let first = store.hold(held.clone(), 500);
+        let second = store.next(&held, 1000);
+        assert_ne!(first, second);
+        assert_eq!(store.get(&first, &anonymous).unwrap().offset, 500);
+        assert_eq!(store.get(&second, &anonymous).unwrap().offset, 1000);
+        assert_eq!(store.held(), 1);
+    }
+
+    #[tokio::test]
+    async fn closing_a_result_drops_all_its_tokens() {
+        let store = store();
+        let anonymous = CursorOwner::default();
+        let held = result(anonymous.clone());
+        let first = store.hold(held.clone(), 500);
+        let second = store.next(&held, 1000);
+        store.close(&held);
+        assert!(store.get(&first, &anonymous).is_none());
+        assert!(store.get(&second, &anonymous).is_none());
+        assert_eq!(store.held(), 0);
+    }
+
+    #[tokio::test]
+    async fn holds_at_most_max_held_results() {
+        let store = store();
+        let anonymous = CursorOwner::default();
+        let oldest = store.hold(result(anonymous.clone()), 500);
+        let middle = store.hold(result(anonymous.clone()), 500);
+        let newest = store.hold(result(anonymous.clone()), 500);
+        assert_eq!(store.held(), 2);
+        assert!(store.get(&oldest, &anonymous).is_none());
+        assert!(store.get(&middle, &anonymous).is_some());
+        assert!(store.get(&newest, &anonymous).is_some());
+    }
+
+    #[tokio::test]
+    async fn closes_the_results_of_a_session() {
+        let store = store();
+        let s1 = owner(Some("s1"), None, None);
+        let s2 = owner(Some("s2"), None, None);
+        let first = store.hold(result(s1.clone()), 500);
+        let second = store.hold(result(s2.clone()), 500);
+        store.close_session("s1");
+        assert!(store.get(&first, &s1).is_none());
+        assert!(store.get(&second, &s2).is_some());
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use serde_json::{json, Value};
+use tracing::{error, warn};
+use crate::error::BridgeError;
+use crate::handlers::prompts::{get_prompt, list_prompts};
+use crate::handlers::resources::{list_resource_templates, list_resources, read_resource};
+use crate::mcp::{McpRequest, McpResponse, ProtocolFeatures};
+use crate::progress::Progress;
+use crate::registry::CallContext;
+use crate::state::AppState;
+
+/// Dispatch JSON-RPC commun à tous les transports (SSE, HTTP, stdio).
+///
+/// Renvoie `None` pour les notifications, qui n'ont jamais de réponse. Les
+/// erreurs de protocole deviennent des `error` JSON-RPC ; celles des outils
+/// restent des résultats `isError`.
+pub async fn handle_request(
+    state: &AppState,
+    ctx: &CallContext,
+    payload: McpRequest,
+) -> Option<McpResponse> {
+    if payload.method == "notifications/cancelled" {
+        handle_cancelled(state, ctx, payload.params.as_ref());
+    }
+    let request_id = payload.id.clone()?;
+    // Abandonnée sans `finish` si le transport abandonne ce futur (client parti).
+    let in_flight = state.in_flight.register(ctx, &request_id);
+    let ctx = &CallContext {
+        request: Some(in_flight.handle()),
+        progress: Progress::from_params(
+            payload.params.as_ref(),
+            ctx.notifier.as_ref(),
+            ctx.features(),
+        ),
+        ..ctx.clone()
+    };
+
+    let result = match payload.method.as_str() {
+        "initialize" => Ok(handle_initialize(&payload)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(state.tools.list(ctx)),
+        "tools/call" => handle_call_tool(state, ctx, payload.params.as_ref()).await,
+        "resources/list" => list_resources(state, ctx).await,
+        "resources/templates/list" => Ok(list_resource_templates()),
+        "resources/read" => handle_read_resource(state, ctx, payload.params.as_ref()).await,
+        "prompts/list" => Ok(list_prompts()),
+        "prompts/get" => handle_get_prompt(payload.params.as_ref()),
+        method => Err(BridgeError::MethodNotFound(method.into())),
+    };
+
+    let cancelled = in_flight.handle().is_cancelled();
+    in_flight.finish();
+    // Une requête annulée n'a pas de réponse.
+    if cancelled {
+        return None;
+    }
+    Some(match result {
+        Ok(result) => McpResponse::success(request_id, result),
+        Err(e) => McpResponse::error(request_id, e.into()),
+    })
+}
+
+/// Répond avec la version du protocole du client si elle est supportée, sinon
+/// la plus récente ; `serverInfo` vient des métadonnées du crate.
+pub fn handle_initialize(payload: &McpRequest) -> Value {
+    let version = payload.negotiated_protocol_version();
+    let mut server_info = json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    if ProtocolFeatures::of(version).titles {
+        server_info["title"] = "PostgreSQL MCP bridge".into();
+    }
+    json!({
+        "protocolVersion": version,
+        "capabilities": {
+            "tools": { "listChanged": true },
+            "resources": { "subscribe": false, "listChanged": false },
+            "prompts": { "listChanged": false }
+        },
+        "serverInfo": server_info
+    })
+}
+
+async fn handle_call_tool(
+    state: &AppState,
+    ctx: &CallContext,
+    params: Option<&Value>,
+) -> Result<Value, BridgeError> {
+    let tool_name = params
+        .and_then(|p| p.get("name")?.as_str())
+        .ok_or_else(|| BridgeError::InvalidParams("Missing tool name".into()))?;
+    let args = match params.and_then(|p| p.get("arguments")) {
+        None | Some(Value::Null) => json!({}),
+        Some(args @ Value::Object(_)) => args.clone(),
+        Some(_) => {
+            return Err(BridgeError::InvalidParams("Tool arguments must be an object".into()));
+        }
+    };
+    let ctx = &CallContext { query: Some(Arc::default()), ..ctx.clone() };
+    // Abandonnée inachevée avec ce futur si le transport renonce à la requête.
+    let audit = state.audit.start(ctx, tool_name, &args);
+    let outcome = match state.tools.get(tool_name) {
+        None => Err(BridgeError::InvalidParams(format!("Unknown tool: {tool_name}"))),
+        Some(_) if !ctx.allows_tool(tool_name) => {
+            warn!(tool = tool_name, principal = ctx.principal_name(), "Tool not allowed for this token");
+            Err(BridgeError::InvalidParams(format!("Tool not allowed for this token: {tool_name}")))
+        }
+        Some(tool) => Ok(tool.call(state, ctx, &args).await),
+    };
+    let error = match &outcome {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) | Err(e) => Some(e.to_string()),
+    };
+    audit.finish(error);
+
+    Ok(match outcome? {
+        Ok(output) => {
+            let mut result = json!({ "content": [{ "type": "text", "text": output.text }] });
+            if ctx.features().structured_output && let Some(structured) = output.structured {
+                result["structuredContent"] = structured;
+            }
+            result
+        }
+        Err(e) => {
+            error!(error = %e, tool = tool_name, "Tool call failed");
+            json!({ "isError": true, "content": [{ "type": "text", "text": e.to_string() }] })
+        }
+    })
+}
+
+async fn handle_read_resource(
+    state: &AppState,
+    ctx: &CallContext,
+    params: Option<&Value>,
+) -> Result<Value, BridgeError> {
+    let uri = params
+        .and_then(|p| p.get("uri")?.as_str())
+        .ok_or_else(|| BridgeError::InvalidParams("Missing resource uri".into()))?;
+    read_resource(state, ctx, uri).await
+}
+
+/// `notifications/cancelled` — annule une requête de la même session.
+fn handle_cancelled(state: &AppState, ctx: &CallContext, params: Option<&Value>) {
+    match params.and_then(|p| p.get("requestId")) {
+        Some(id) => state.in_flight.cancel(ctx, id),
+        None => warn!("notifications/cancelled without requestId"),
+    }
+}
+
+fn handle_get_prompt(params: Option<&Value>) -> Result<Value, BridgeError> {
+    let name = params
+        .and_then(|p| p.get("name")?.as_str())
+        .ok_or_else(|| BridgeError::InvalidParams("Missing prompt name".into()))?;
+    get_prompt(name, params.and_then(|p| p.get("arguments")))
+}
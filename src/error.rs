@@ -1,5 +1,7 @@
 use axum::response::{IntoResponse, Response};
 use axum::http::StatusCode;
+use serde_json::json;
+use crate::mcp::{JsonRpcError, INTERNAL_ERROR, INVALID_PARAMS, METHOD_NOT_FOUND, RESOURCE_NOT_FOUND};
 
 #[derive(thiserror::Error, Debug)]
 pub enum BridgeError {
@@ -7,6 +9,12 @@ pub enum BridgeError {
     Database(#[from] sqlx::Error),
     #[error("Backend API error: {0}")]
     Api(String),
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    #[error("Resource not found: {0}")]
+    ResourceNotFound(String),
 }
 
 impl IntoResponse for BridgeError {
@@ -14,3 +22,29 @@ impl IntoResponse for BridgeError {
         (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
     }
 }
+
+impl From<BridgeError> for JsonRpcError {
+    fn from(e: BridgeError) -> Self {
+        match e {
+            BridgeError::MethodNotFound(method) => {
+                JsonRpcError::new(METHOD_NOT_FOUND, format!("Method not found: {method}"))
+                    .with_data(json!({ "method": method }))
+            }
+            BridgeError::InvalidParams(msg) => JsonRpcError::new(INVALID_PARAMS, msg),
+            BridgeError::ResourceNotFound(uri) => {
+                JsonRpcError::new(RESOURCE_NOT_FOUND, format!("Resource not found: {uri}"))
+                    .with_data(json!({ "uri": uri }))
+            }
+            BridgeError::Database(db) => {
+                let sqlstate = db
+                    .as_database_error()
+                    .and_then(|d| d.code())
+                    .map(|c| c.into_owned());
+                JsonRpcError::new(INTERNAL_ERROR, format!("Database error: {db}"))
+                    .with_data(json!({ "kind": "database", "sqlstate": sqlstate }))
+            }
+            BridgeError::Api(msg) => JsonRpcError::new(INTERNAL_ERROR, msg)
+                .with_data(json!({ "kind": "api" })),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{collections::BTreeSet, str::FromStr};
+use serde_json::{json, Value};
+use crate::error::BridgeError;
+
+/// Format du texte renvoyé par les outils de requête.
+///
+/// Les lignes viennent de `row_to_json` : chacune est un objet dont les clés sont
+/// les colonnes du résultat, dans l'ordre du SELECT (serde_json garde l'ordre).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tableau d'objets indenté.
+    Json,
+    /// Même tableau sur une ligne.
+    JsonCompact,
+    /// `{"columns": [...], "rows": [[...], ...]}` : noms de colonnes une seule fois.
+    Columnar,
+    Csv,
+    Markdown,
+}
+
+pub const FORMAT_NAMES: &[&str] = &["json", "json_compact", "columnar", "csv", "markdown"];
+
+impl FromStr for OutputFormat {
+    type Err = BridgeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "json_compact" => Ok(Self::JsonCompact),
+            "columnar" => Ok(Self::Columnar),
+            "csv" => Ok(Self::Csv),
+            "markdown" => Ok(Self::Markdown),
+            other => Err(BridgeError::InvalidParams(format!(
+                "Unknown format '{other}', expected one of: {}",
+                FORMAT_NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Lit l'argument optionnel `format` de l'outil, sinon `default`.
+    pub fn from_args(args: &Value, default: Self) -> Result<Self, BridgeError> {
+        match args.get("format") {
+            None | Some(Value::Null) => Ok(default),
+            Some(Value::String(s)) => s.parse(),
+            Some(_) => Err(BridgeError::InvalidParams("'format' must be a string".into())),
+        }
+    }
+
+    /// Schéma JSON de l'argument `format`, pour les schémas d'entrée des outils.
+    pub fn schema() -> Value {
+        json!({
+            "type": "string",
+            "enum": FORMAT_NAMES,
+            "description": concat!(
+                "Output format; omit for the server default. columnar, csv and markdown ",
+                "list column names once and use far less context than json on wide results."
+            )
+        })
+    }
+
+    pub fn render(self, rows: &[Value]) -> String {
+        match self {
+            Self::Json => serde_json::to_string_pretty(rows).unwrap_or_else(|_| "[]".into()),
+            Self::JsonCompact => serde_json::to_string(rows).unwrap_or_else(|_| "[]".into()),
+            Self::Columnar => {
+                let columns = columns(rows);
+                let rows: Vec<Vec<&Value>> = rows.iter().map(|r| cells(r, &columns)).collect();
+                serde_json::to_string(&json!({ "columns": columns, "rows": rows }))
+                    .unwrap_or_else(|_| "{}".into())
+            }
+            Self::Csv => render_csv(rows),
+            Self::Markdown => render_markdown(rows),
+        }
+    }
+}
+
+fn columns(rows: &[Value]) -> Vec<&str> {
+    rows.first()
+        .and_then(Value::as_object)
+        .map(|row| row.keys().map(String::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn cells<'a>(row: &'a Value, columns: &[&str]) -> Vec<&'a Value> {
+    columns.iter().map(|c| row.get(*c).unwrap_or(&Value::Null)).collect()
+}
+
+/// Chaînes telles quelles, `null` vide, le reste en JSON compact.
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn render_csv(rows: &[Value]) -> String {
+    let columns = columns(rows);
+    if columns.is_empty() {
+        return "No rows.".into();
+    }
+    let escape = |field: &str| {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+    let mut out = columns.iter().map(|c| escape(c)).collect::<Vec<_>>().join(",");
+    for row in rows {
+        out.push('\n');
+        let line: Vec<String> = cells(row, &columns)
+            .into_iter()
+            .map(|v| escape(&cell_text(v)))
+            .collect();
+        out.push_str(&line.join(","));
+    }
+    out
+}
+
+fn render_markdown(rows: &[Value]) -> String {
+    let columns = columns(rows);
+    if columns.is_empty() {
+        return "_No rows._".into();
+    }
+    let escape = |field: &str| field.replace('|', "\\|").replace(['\r', '\n'], " ");
+    let mut out = format!(
+        "| {} |\n|{}|",
+        columns.iter().map(|c| escape(c)).collect::<Vec<_>>().join(" | "),
+        vec!["---"; columns.len()].join("|")
+    );
+    for row in rows {
+        let line: Vec<String> = cells(row, &columns)
+            .into_iter()
+            .map(|v| escape(&cell_text(v)))
+            .collect();
+        out.push_str(&format!("\n| {} |", line.join(" | ")));
+    }
+    out
+}
+
+/// Ce que `truncate_cells` a coupé dans une page.
+#[derive(Default)]
+pub struct CellTruncation {
+    /// Longueur à laquelle les cellules ont été coupées.
+    pub max_chars: usize,
+    pub cells: usize,
+    pub chars: usize,
+    pub columns: BTreeSet<String>,
+}
+
+/// Coupe les cellules texte de plus de `max_chars` caractères et les termine par
+/// un marqueur `…[+N chars]`. `0` désactive la limite.
+pub fn truncate_cells(rows: &mut [Value], max_chars: usize) -> CellTruncation {
+    let mut report = CellTruncation { max_chars, ..CellTruncation::default() };
+    if max_chars == 0 {
+        return report;
+    }
+    for row in rows.iter_mut().filter_map(Value::as_object_mut) {
+        for (column, cell) in row.iter_mut() {
+            let Value::String(text) = cell else { continue };
+            let len = text.chars().count();
+            if len <= max_chars {
+                continue;
+            }
+            let kept: String = text.chars().take(max_chars).collect();
+            *text = format!("{kept}…[+{} chars]", len - max_chars);
+            report.cells += 1;
+            report.chars += len - max_chars;
+            report.columns.insert(column.clone());
+        }
+    }
+    report
+}
+
+/// Nombre de premières lignes parmi `len` dont la page, de taille `size(n)` pour
+/// `n` lignes, tient dans `max_bytes`. Toujours au moins une ligne s'il y en a,
+/// pour que la pagination avance.
+pub fn fit_rows(len: usize, max_bytes: usize, size: impl Fn(usize) -> usize) -> usize {
+    if len <= 1 || size(len) <= max_bytes {
+        return len;
+    }
+    // La taille croît avec le nombre de lignes : recherche dichotomique du plus long préfixe.
+    let (mut fits, mut too_big) = (1, len);
+    while too_big - fits > 1 {
+        let mid = (fits + too_big) / 2;
+        if size(mid) <= max_bytes {
+            fits = mid;
+        } else {
+            too_big = mid;
+        }
+    }
+    fits
+}
+
+/// Longueur de cellule qui fait tenir `rows` dans `max_bytes`, pour une ligne seule
+/// trop grande : la plus grande pour laquelle `size` des cellules coupées tient,
+/// ou `1` si aucune ne suffit (les nombres et objets JSON ne sont pas coupés).
+pub fn fit_cells(
+    rows: &[Value],
+    max_bytes: usize,
+    size: impl Fn(&[Value], &CellTruncation) -> usize,
+) -> usize {
+    let cut = |max_chars| {
+        let mut rows = rows.to_vec();
+        let report = truncate_cells(&mut rows, max_chars);
+        size(&rows, &report)
+    };
+    let longest = rows
+        .iter()
+        .filter_map(Value::as_object)
+        .flat_map(|row| row.values())
+        .filter_map(Value::as_str)
+        .map(|text| text.chars().count())
+        .max()
+        .unwrap_or(0);
+    let (mut fits, mut too_big) = (1, longest.max(1));
+    if cut(fits) > max_bytes {
+        return fits;
+    }
+    while too_big - fits > 1 {
+        let mid = (fits + too_big) / 2;
+        if cut(mid) <= max_bytes {
+            fits = mid;
+        } else {
+            too_big = mid;
+        }
+    }
+    fits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!({ "ticker": "AAPL", "close": 150.5, "note": "a, \"b\"" }),
+            json!({ "ticker": "MSFT", "close": null, "note": "x|y\nz" }),
+        ]
+    }
+
+    #[test]
+    fn parses_every_name() {
+        for name in FORMAT_NAMES {
+            assert!(name.parse::<OutputFormat>().is_ok(), "{name}");
+        }
+        assert!("xml".parse::<OutputFormat>().is_err());
+        assert_eq!(OutputFormat::from_args(&json!({}), OutputFormat::Csv).unwrap(), OutputFormat::Csv);
+        assert_eq!(
+            OutputFormat::from_args(&json!({ "format": "markdown" }), OutputFormat::Json).unwrap(),
+            OutputFormat::Markdown
+        );
+        assert!(OutputFormat::from_args(&json!({ "format": 1 }), OutputFormat::Json).is_err());
+    }
+
+    #[test]
+    fn renders_columnar_in_select_order() {
+        let text = OutputFormat::Columnar.render(&rows());
+        assert_eq!(
+            text,
+            r#"{"columns":["ticker","close","note"],"rows":[["AAPL",150.5,"a, \"b\""],["MSFT",null,"x|y\nz"]]}"#
+        );
+    }
+
+    #[test]
+    fn renders_csv_with_quoting() {
+        assert_eq!(
+            OutputFormat::Csv.render(&rows()),
+            "ticker,close,note\nAAPL,150.5,\"a, \"\"b\"\"\"\nMSFT,,\"x|y\nz\""
+        );
+        assert_eq!(OutputFormat::Csv.render(&[]), "No rows.");
+    }
+
+    #[test]
+    fn renders_markdown_with_escaping() {
+        assert_eq!(
+            OutputFormat::Markdown.render(&rows()),
+            "| ticker | close | note |\n|---|---|---|\n| AAPL | 150.5 | a, \"b\" |\n| MSFT |  | x\\|y z |"
+        );
+    }
+
+    #[test]
+    fn truncates_long_cells() {
+        let mut rows = vec![json!({ "summary": "abcdefgh", "n": 12345678 })];
+        let report = truncate_cells(&mut rows, 3);
+        assert_eq!(rows[0]["summary"], "abc…[+5 chars]");
+        assert_eq!(rows[0]["n"], 12345678);
+        assert_eq!((report.cells, report.chars), (1, 5));
+        assert!(report.columns.contains("summary"));
+        assert_eq!(truncate_cells(&mut rows, 0).cells, 0);
+    }
+
+    #[test]
+    fn fits_rows_in_the_budget() {
+        let rows: Vec<Value> = (0..100).map(|i| json!({ "i": i })).collect();
+        let size = |n: usize| OutputFormat::JsonCompact.render(&rows[..n]).len();
+        let fitted = fit_rows(rows.len(), 100, size);
+        assert!(size(fitted) <= 100);
+        assert!(size(fitted + 1) > 100);
+        // Jamais zéro ligne, pour que la pagination avance.
+        assert_eq!(fit_rows(rows.len(), 1, size), 1);
+        assert_eq!(fit_rows(rows.len(), usize::MAX, size), 100);
+        // Ce qui accompagne les lignes compte aussi.
+        assert_eq!(fit_rows(rows.len(), 100, |n| size(n) + 100), 1);
+    }
+
+    #[test]
+    fn cuts_cells_to_fit_a_single_row() {
+        let rows = vec![json!({ "id": 1, "body": "x".repeat(1000) })];
+        let size = |rows: &[Value], _: &CellTruncation| {
+            OutputFormat::JsonCompact.render(rows).len()
+        };
+        let max_chars = fit_cells(&rows, 100, size);
+        let mut cut = rows.clone();
+        truncate_cells(&mut cut, max_chars);
+        assert!(size(&cut, &CellTruncation::default()) <= 100);
+        let mut longer = rows.clone();
+        truncate_cells(&mut longer, max_chars + 1);
+        assert!(size(&longer, &CellTruncation::default()) > 100);
+        // Rien ne suffit : les cellules sont coupées au plus court.
+        assert_eq!(fit_cells(&rows, 1, size), 1);
+    }
+}
@@ -0,0 +1,700 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::ControlFlow,
+};
+use sqlparser::ast::{
+    Expr, FunctionArg, FunctionArgExpr, FunctionArguments, Ident, JoinConstraint, JoinOperator,
+    ObjectName, Query, SelectItem, SetExpr, Spanned, Statement, TableFactor, TableWithJoins,
+    Value, Visit, Visitor,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::Span;
+use crate::error::BridgeError;
+
+/// Fonctions refusées même dans un SELECT : effets de bord sur le serveur,
+/// accès fichiers, verrous, séquences, ou exécution de SQL dynamique.
+const DENIED_FUNCTIONS: &[&str] = &[
+    "pg_terminate_backend", "pg_cancel_backend", "pg_reload_conf", "pg_rotate_logfile",
+    "pg_sleep", "pg_sleep_for", "pg_sleep_until",
+    "pg_read_file", "pg_read_binary_file", "pg_ls_dir", "pg_stat_file", "pg_file_write",
+    "lo_import", "lo_export", "lo_unlink", "lo_create", "lo_from_bytea", "lo_put",
+    "dblink", "dblink_exec", "dblink_connect", "dblink_send_query",
+    "set_config", "nextval", "setval",
+    "pg_advisory_lock", "pg_advisory_lock_shared", "pg_advisory_xact_lock",
+    "pg_advisory_xact_lock_shared", "pg_try_advisory_lock", "pg_try_advisory_lock_shared",
+    "pg_try_advisory_xact_lock", "pg_try_advisory_xact_lock_shared",
+    "pg_notify", "pg_switch_wal", "pg_create_restore_point", "pg_promote",
+    "pg_logical_emit_message", "query_to_xml", "query_to_xml_and_xmlschema",
+    "query_to_xmlschema", "cursor_to_xml",
+    // Lisent une table désignée par une chaîne, hors de portée de l'analyse.
+    "table_to_xml", "table_to_xml_and_xmlschema", "table_to_xmlschema",
+    "schema_to_xml", "schema_to_xml_and_xmlschema", "schema_to_xmlschema",
+    "database_to_xml", "database_to_xml_and_xmlschema", "database_to_xmlschema",
+    "ts_stat", "ts_rewrite",
+    // Objets larges, journaux et slots de réplication lus hors des tables.
+    "lo_get", "lo_open", "loread",
+    "pg_ls_logdir", "pg_ls_waldir", "pg_ls_tmpdir", "pg_ls_archive_statusdir",
+    "pg_ls_logicalsnapdir", "pg_ls_logicalmapdir", "pg_ls_replslotdir",
+    "pg_logical_slot_get_changes", "pg_logical_slot_peek_changes",
+    "pg_logical_slot_get_binary_changes", "pg_logical_slot_peek_binary_changes",
+    "pg_replication_slot_advance", "pg_create_logical_replication_slot",
+    "pg_create_physical_replication_slot", "pg_drop_replication_slot",
+    "txid_current", "pg_current_xact_id",
+];
+
+/// Familles de fonctions refusées par préfixe (`pg_stat_reset_shared`, ...).
+const DENIED_PREFIXES: &[&str] = &["pg_stat_reset"];
+
+/// Accepte `sql` s'il s'agit d'une seule requête pure : ni CTE modifiante, ni
+/// `SELECT ... INTO`, ni verrou, ni fonction refusée. Renvoie le SQL normalisé,
+/// celui qui est exécuté.
+pub fn validate_read_only(sql: &str) -> Result<String, BridgeError> {
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .map_err(|e| reject(format!("could not parse the query ({e})")))?;
+
+    let statement = match statements.as_slice() {
+        [statement] => statement,
+        [] => return Err(reject("the query is empty".into())),
+        [_, second, ..] => {
+            return Err(reject(format!(
+                "only a single statement is allowed, found a second one: `{}`",
+                first_words(&second.to_string())
+            )))
+        }
+    };
+    if !matches!(statement, Statement::Query(_)) {
+        return Err(reject(format!(
+            "only SELECT and WITH...SELECT queries are allowed, got `{}`",
+            first_words(&statement.to_string())
+        )));
+    }
+
+    match statement.visit(&mut ReadOnlyVisitor) {
+        ControlFlow::Break(reason) => Err(reject(reason)),
+        ControlFlow::Continue(()) => Ok(statement.to_string()),
+    }
+}
+
+/// Relations lues par une requête déjà passée par `validate_read_only`, telles
+/// qu'écrites (`quotes`, `public.quotes`), sans les CTE ni les fonctions de table.
+pub fn referenced_tables(sql: &str) -> Result<BTreeSet<String>, BridgeError> {
+    Ok(query_references(sql)?.relations)
+}
+
+/// Ce que lit une requête, pour les contrôles d'accès de `policy.rs`. Les noms sont
+/// normalisés comme dans PostgreSQL ; l'analyse est syntaxique et liste plutôt trop que pas assez.
+#[derive(Default)]
+pub struct QueryReferences {
+    /// Relations telles qu'écrites, sans les CTE ni les fonctions de table.
+    pub relations: BTreeSet<String>,
+    /// Alias (ou nom s'il n'y en a pas) vers les relations qu'il désigne.
+    pub aliases: BTreeMap<String, BTreeSet<String>>,
+    /// Relations dont l'alias renomme les colonnes (`accounts AS a(x, y)`).
+    pub renamed: BTreeSet<String>,
+    pub wildcards: Vec<Wildcard>,
+    /// Références de colonnes (ou de lignes entières, `to_json(a)`), avec leur qualificatif.
+    pub columns: Vec<(Option<String>, String)>,
+    /// `NATURAL JOIN` compare chaque paire de colonnes de même nom.
+    pub natural_join: bool,
+    /// Références de colonnes dont dépendent les lignes renvoyées ou leur ordre :
+    /// `WHERE`, `JOIN ... ON` / `USING`, `HAVING` et `ORDER BY` (alias et numéros
+    /// de colonne résolus vers leur expression), sous-requêtes comprises. `*` pour
+    /// un numéro de colonne qui désigne un `*`.
+    pub filters: BTreeSet<(Option<String>, String)>,
+}
+
+pub enum Wildcard {
+    /// `*`, avec les relations du `FROM` de son `SELECT` (ou `TABLE x`).
+    From(BTreeSet<String>),
+    /// `alias.*`
+    Of(String),
+}
+
+pub fn query_references(sql: &str) -> Result<QueryReferences, BridgeError> {
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .map_err(|e| reject(format!("could not parse the query ({e})")))?;
+    let mut visitor = TableVisitor::default();
+    let _ = statements.visit(&mut visitor);
+    Ok(visitor.refs)
+}
+
+fn reject(reason: String) -> BridgeError {
+    BridgeError::Api(format!("Read-only mode: {reason}."))
+}
+
+fn at(span: Span) -> String {
+    if span.start.line == 0 {
+        String::new()
+    } else {
+        format!(" at line {}, column {}", span.start.line, span.start.column)
+    }
+}
+
+fn first_words(sql: &str) -> String {
+    sql.split_whitespace().take(3).collect::<Vec<_>>().join(" ")
+}
+
+fn denied_function(name: &ObjectName) -> Option<String> {
+    let last = name.0.last()?.value.to_lowercase();
+    (DENIED_FUNCTIONS.contains(&last.as_str())
+        || DENIED_PREFIXES.iter().any(|prefix| last.starts_with(prefix)))
+    .then(|| format!("function `{name}` is not allowed{}", at(name.span())))
+}
+
+struct ReadOnlyVisitor;
+
+impl ReadOnlyVisitor {
+    fn check_body(body: &SetExpr) -> ControlFlow<String> {
+        match body {
+            SetExpr::Select(select) => {
+                if let Some(into) = &select.into {
+                    return ControlFlow::Break(format!(
+                        "`SELECT ... INTO {}` is not allowed{}",
+                        into.name,
+                        at(select.span())
+                    ));
+                }
+                ControlFlow::Continue(())
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                Self::check_body(left)?;
+                Self::check_body(right)
+            }
+            // Les requêtes imbriquées passent par `pre_visit_query`.
+            SetExpr::Query(_) | SetExpr::Values(_) | SetExpr::Table(_) => ControlFlow::Continue(()),
+            // Tout le reste (INSERT, UPDATE, ou ce qu'ajouterait le parseur) écrit.
+            other => ControlFlow::Break(format!(
+                "data-modifying statement `{}` is not allowed",
+                first_words(&other.to_string())
+            )),
+        }
+    }
+
+    fn is_data_modifying(body: &SetExpr) -> bool {
+        !matches!(
+            body,
+            SetExpr::Select(_) | SetExpr::Query(_) | SetExpr::SetOperation { .. }
+                | SetExpr::Values(_) | SetExpr::Table(_)
+        )
+    }
+}
+
+impl Visitor for ReadOnlyVisitor {
+    type Break = String;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<String> {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                if Self::is_data_modifying(&cte.query.body) {
+                    return ControlFlow::Break(format!(
+                        "data-modifying CTE `{}` is not allowed{}",
+                        cte.alias.name,
+                        at(cte.span())
+                    ));
+                }
+            }
+        }
+        if let Some(lock) = query.locks.first() {
+            return ControlFlow::Break(format!(
+                "locking clause `{lock}` is not allowed{}",
+                at(query.span())
+            ));
+        }
+        Self::check_body(&query.body)
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<String> {
+        match table_factor {
+            TableFactor::Table { name, args: Some(_), .. }
+            | TableFactor::Function { name, .. } => match denied_function(name) {
+                Some(reason) => ControlFlow::Break(reason),
+                None => ControlFlow::Continue(()),
+            },
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<String> {
+        if let Expr::Function(function) = expr
+            && let Some(reason) = denied_function(&function.name)
+        {
+            return ControlFlow::Break(reason);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Collecte les relations, sauf les références aux CTE visibles. Une CTE n'est
+/// visible qu'après sa propre définition (toutes avec `RECURSIVE`), et seulement
+/// dans la requête qui la déclare, comme dans PostgreSQL.
+#[derive(Default)]
+struct TableVisitor {
+    refs: QueryReferences,
+    /// Noms des fonctions de table (`generate_series(1, 3)`), identifiés par adresse :
+    /// le visiteur les présente aussi comme relations, et une vraie table peut
+    /// porter le même nom.
+    functions: Vec<*const ObjectName>,
+    scopes: Vec<CteScope>,
+}
+
+#[derive(Default)]
+struct CteScope {
+    /// Corps de CTE pas encore visités, identifiés par adresse, avec leur nom.
+    pending: Vec<(*const Query, String)>,
+    visible: BTreeSet<String>,
+}
+
+impl TableVisitor {
+    fn is_cte(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.visible.contains(name))
+    }
+
+    /// Dans le corps de la requête la plus interne, où toutes ses CTE sont définies.
+    fn is_cte_in_body(&self, name: &str) -> bool {
+        self.is_cte(name)
+            || self
+                .scopes
+                .last()
+                .is_some_and(|scope| scope.pending.iter().any(|(_, cte)| cte == name))
+    }
+
+    /// `*`, colonnes `USING`, jointures `NATURAL` et filtres d'un corps de requête ;
+    /// le reste est atteint par le visiteur.
+    fn collect_body(&mut self, body: &SetExpr) {
+        match body {
+            SetExpr::Select(select) => {
+                let mut from = BTreeSet::new();
+                for table in &select.from {
+                    self.collect_from(table, &mut from);
+                }
+                for filter in select.selection.iter().chain(&select.having) {
+                    self.collect_filter(filter);
+                }
+                for item in &select.projection {
+                    match item {
+                        SelectItem::Wildcard(_) => {
+                            self.refs.wildcards.push(Wildcard::From(from.clone()))
+                        }
+                        SelectItem::QualifiedWildcard(name, _) => {
+                            self.refs.wildcards.push(Wildcard::Of(relation_name(name)))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                self.collect_body(left);
+                self.collect_body(right);
+            }
+            // `TABLE x` équivaut à `SELECT * FROM x` ; le parseur perd les guillemets du nom.
+            SetExpr::Table(table) => {
+                let Some(name) = &table.table_name else { return };
+                let name = match &table.schema_name {
+                    Some(schema) => format!("{schema}.{name}").to_lowercase(),
+                    None => name.to_lowercase(),
+                };
+                if name.contains('.') || !self.is_cte_in_body(&name) {
+                    self.refs.relations.insert(name.clone());
+                    self.refs.wildcards.push(Wildcard::From(BTreeSet::from([name])));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn collect_from(&mut self, table: &TableWithJoins, from: &mut BTreeSet<String>) {
+        self.collect_factor(&table.relation, from);
+        for join in &table.joins {
+            self.collect_factor(&join.relation, from);
+            match join_constraint(&join.join_operator) {
+                Some(JoinConstraint::Using(columns)) => {
+                    let columns = columns.iter().map(|c| (None, ident_name(c)));
+                    self.refs.columns.extend(columns.clone());
+                    self.refs.filters.extend(columns);
+                }
+                Some(JoinConstraint::On(on)) => self.collect_filter(on),
+                Some(JoinConstraint::Natural) => self.refs.natural_join = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_factor(&mut self, factor: &TableFactor, from: &mut BTreeSet<String>) {
+        match factor {
+            TableFactor::Table { name, args: None, .. } => {
+                let name = relation_name(name);
+                if name.contains('.') || !self.is_cte_in_body(&name) {
+                    from.insert(name);
+                }
+            }
+            TableFactor::NestedJoin { table_with_joins, .. } => {
+                self.collect_from(table_with_joins, from)
+            }
+            _ => {}
+        }
+    }
+
+    fn collect_filter(&mut self, expr: &Expr) {
+        let mut columns = ColumnVisitor::default();
+        let _ = expr.visit(&mut columns);
+        self.refs.filters.extend(columns.0);
+    }
+
+    /// `ORDER BY` d'une requête : un nom d'alias ou un numéro désigne l'expression
+    /// correspondante du `SELECT`, comme dans PostgreSQL.
+    fn collect_order_by(&mut self, query: &Query) {
+        let Some(order_by) = &query.order_by else { return };
+        let projection = match query.body.as_ref() {
+            SetExpr::Select(select) => select.projection.as_slice(),
+            _ => &[],
+        };
+        for item in &order_by.exprs {
+            let selected = match &item.expr {
+                Expr::Identifier(ident) => projection.iter().find_map(|p| match p {
+                    SelectItem::ExprWithAlias { expr, alias } if ident_name(alias) == ident_name(ident) => {
+                        Some(Some(expr))
+                    }
+                    _ => None,
+                }),
+                // Après un `*`, le numéro ne désigne plus un élément connu du `SELECT`.
+                Expr::Value(Value::Number(n, _)) => n.parse::<usize>().ok().and_then(|n| {
+                    let before = &projection[..n.min(projection.len())];
+                    match before.iter().any(is_wildcard) {
+                        true => Some(None),
+                        false => match projection.get(n.checked_sub(1)?)? {
+                            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                                Some(Some(expr))
+                            }
+                            _ => None,
+                        },
+                    }
+                }),
+                _ => None,
+            };
+            match selected {
+                Some(Some(expr)) => self.collect_filter(expr),
+                Some(None) => {
+                    self.refs.filters.insert((None, "*".into()));
+                }
+                None => self.collect_filter(&item.expr),
+            }
+        }
+    }
+}
+
+/// Références de colonnes d'une expression, sous-requêtes comprises.
+#[derive(Default)]
+struct ColumnVisitor(BTreeSet<(Option<String>, String)>);
+
+impl Visitor for ColumnVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        if let Some(column) = column_reference(expr) {
+            self.0.insert(column);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+fn is_wildcard(item: &SelectItem) -> bool {
+    matches!(item, SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..))
+}
+
+/// Colonne (ou ligne entière) désignée par `expr`, avec son qualificatif.
+fn column_reference(expr: &Expr) -> Option<(Option<String>, String)> {
+    match expr {
+        Expr::Identifier(ident) => Some((None, ident_name(ident))),
+        Expr::CompoundIdentifier(parts) => match parts.as_slice() {
+            [qualifier @ .., column] => {
+                let qualifier = qualifier.iter().map(ident_name).collect::<Vec<_>>().join(".");
+                Some((Some(qualifier), ident_name(column)))
+            }
+            [] => None,
+        },
+        _ => None,
+    }
+}
+
+fn join_constraint(operator: &JoinOperator) -> Option<&JoinConstraint> {
+    match operator {
+        JoinOperator::Inner(c)
+        | JoinOperator::LeftOuter(c)
+        | JoinOperator::RightOuter(c)
+        | JoinOperator::FullOuter(c)
+        | JoinOperator::Semi(c)
+        | JoinOperator::LeftSemi(c)
+        | JoinOperator::RightSemi(c)
+        | JoinOperator::Anti(c)
+        | JoinOperator::LeftAnti(c)
+        | JoinOperator::RightAnti(c)
+        | JoinOperator::AsOf { constraint: c, .. } => Some(c),
+        JoinOperator::CrossJoin | JoinOperator::CrossApply | JoinOperator::OuterApply => None,
+    }
+}
+
+fn ident_name(ident: &Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+/// `schema.table` tel qu'écrit, parties sans guillemets en minuscules comme PostgreSQL.
+fn relation_name(name: &ObjectName) -> String {
+    name.0.iter().map(ident_name).collect::<Vec<_>>().join(".")
+}
+
+impl Visitor for TableVisitor {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        let mut scope = CteScope::default();
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                let name = cte.alias.name.value.to_lowercase();
+                match with.recursive {
+                    true => {
+                        scope.visible.insert(name);
+                    }
+                    false => scope.pending.push((&*cte.query as *const Query, name)),
+                }
+            }
+        }
+        self.scopes.push(scope);
+        self.collect_body(&query.body);
+        self.collect_order_by(query);
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        self.scopes.pop();
+        if let Some(parent) = self.scopes.last_mut()
+            && let Some(index) = parent.pending.iter().position(|(body, _)| std::ptr::eq(*body, query))
+        {
+            let (_, name) = parent.pending.remove(index);
+            parent.visible.insert(name);
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<()> {
+        match table_factor {
+            TableFactor::Table { name, args: Some(_), .. } => {
+                self.functions.push(name);
+            }
+            TableFactor::Table { name, alias, args: None, .. } => {
+                let relation = relation_name(name);
+                if name.0.len() == 1 && self.is_cte(&relation) {
+                    return ControlFlow::Continue(());
+                }
+                let mut designators = vec![relation.clone()];
+                match alias {
+                    Some(alias) => {
+                        designators.push(ident_name(&alias.name));
+                        if !alias.columns.is_empty() {
+                            self.refs.renamed.insert(relation.clone());
+                        }
+                    }
+                    None => designators.extend(name.0.last().map(ident_name)),
+                }
+                for designator in designators {
+                    self.refs.aliases.entry(designator).or_default().insert(relation.clone());
+                }
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<()> {
+        if self.functions.iter().any(|&function| std::ptr::eq(function, relation)) {
+            return ControlFlow::Continue(());
+        }
+        let name = relation_name(relation);
+        if relation.0.len() > 1 || !self.is_cte(&name) {
+            self.refs.relations.insert(name);
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        if let Some(column) = column_reference(expr) {
+            self.refs.columns.push(column);
+        }
+        match expr {
+            Expr::QualifiedWildcard(name, _) => {
+                self.refs.wildcards.push(Wildcard::Of(relation_name(name)))
+            }
+            Expr::Function(function) => {
+                let FunctionArguments::List(list) = &function.args else {
+                    return ControlFlow::Continue(());
+                };
+                for arg in &list.args {
+                    if let FunctionArg::Unnamed(FunctionArgExpr::QualifiedWildcard(name))
+                    | FunctionArg::Named { arg: FunctionArgExpr::QualifiedWildcard(name), .. }
+                    | FunctionArg::ExprNamed { arg: FunctionArgExpr::QualifiedWildcard(name), .. } = arg
+                    {
+                        self.refs.wildcards.push(Wildcard::Of(relation_name(name)));
+                    }
+                }
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(sql: &str) -> String {
+        match validate_read_only(sql) {
+            Ok(normalised) => panic!("`{sql}` was accepted as `{normalised}`"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_pure_queries() {
+        for sql in [
+            "SELECT * FROM quotes WHERE ticker = $1",
+            "WITH last AS (SELECT max(date) AS d FROM quotes) SELECT * FROM quotes, last WHERE date = d",
+            "SELECT 1 UNION SELECT 2",
+            "VALUES (1), (2)",
+            "SELECT current_setting('app.user_id', true)",
+        ] {
+            assert!(validate_read_only(sql).is_ok(), "`{sql}` was rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_several_statements() {
+        assert!(rejected("SELECT 1; SELECT 2").contains("single statement"));
+        assert!(rejected("SELECT 1; DROP TABLE quotes").contains("single statement"));
+    }
+
+    #[test]
+    fn rejects_other_statements() {
+        for sql in [
+            "DELETE FROM quotes",
+            "UPDATE quotes SET close = 0",
+            "INSERT INTO quotes DEFAULT VALUES",
+            "SET ROLE postgres",
+            "COPY quotes TO '/tmp/q'",
+        ] {
+            assert!(rejected(sql).contains("only SELECT"), "`{sql}`");
+        }
+    }
+
+    #[test]
+    fn rejects_select_into() {
+        assert!(rejected("SELECT * INTO copy FROM quotes").contains("SELECT ... INTO"));
+        assert!(rejected("SELECT 1 UNION SELECT 2 INTO copy").contains("SELECT ... INTO"));
+    }
+
+    #[test]
+    fn rejects_locking_clauses() {
+        for lock in ["FOR UPDATE", "FOR SHARE", "FOR UPDATE NOWAIT", "FOR SHARE SKIP LOCKED"] {
+            let sql = format!("SELECT * FROM holdings {lock}");
+            assert!(rejected(&sql).contains("locking clause"), "`{sql}`");
+        }
+        // Inconnus de sqlparser 0.53 : refusés car non analysables.
+        rejected("SELECT * FROM holdings FOR NO KEY UPDATE");
+        rejected("SELECT * FROM holdings FOR KEY SHARE");
+        assert!(rejected("SELECT * FROM (SELECT * FROM holdings FOR UPDATE) h").contains("locking clause"));
+    }
+
+    #[test]
+    fn rejects_data_modifying_ctes() {
+        assert!(rejected("WITH d AS (INSERT INTO quotes DEFAULT VALUES RETURNING *) SELECT * FROM d")
+            .contains("data-modifying"));
+        assert!(rejected("WITH d AS (UPDATE quotes SET close = 0 RETURNING *) SELECT * FROM d")
+            .contains("data-modifying"));
+        // Non analysé par sqlparser 0.53 ; quelle qu'en soit la raison, refusé.
+        rejected("WITH d AS (DELETE FROM quotes RETURNING *) SELECT * FROM d");
+    }
+
+    #[test]
+    fn rejects_every_denied_function() {
+        for function in DENIED_FUNCTIONS {
+            let sql = format!("SELECT {function}('x')");
+            assert!(rejected(&sql).contains("is not allowed"), "`{sql}`");
+            let sql = format!("SELECT * FROM {function}('x')");
+            assert!(rejected(&sql).contains("is not allowed"), "`{sql}`");
+        }
+    }
+
+    #[test]
+    fn rejects_denied_prefixes() {
+        for sql in ["SELECT pg_stat_reset()", "SELECT pg_stat_reset_shared('bgwriter')"] {
+            assert!(rejected(sql).contains("is not allowed"), "`{sql}`");
+        }
+    }
+
+    #[test]
+    fn rejects_qualified_and_cased_names() {
+        for sql in [
+            "SELECT PG_CATALOG.pg_read_file('/etc/passwd')",
+            "SELECT pg_catalog.PG_READ_FILE('/etc/passwd')",
+            "SELECT Pg_Sleep(10)",
+            "SELECT \"pg_sleep\"(10)",
+            "SELECT * FROM quotes WHERE close > (SELECT pg_catalog.txid_current())",
+            "SELECT lower(pg_catalog.lo_get(1)::text)",
+        ] {
+            assert!(rejected(sql).contains("is not allowed"), "`{sql}`");
+        }
+    }
+
+    fn filters(sql: &str) -> Vec<String> {
+        let refs = query_references(sql).unwrap();
+        refs.filters
+            .into_iter()
+            .map(|(qualifier, column)| match qualifier {
+                Some(qualifier) => format!("{qualifier}.{column}"),
+                None => column,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collects_the_columns_rows_depend_on() {
+        assert_eq!(filters("SELECT quantity, name FROM holdings"), Vec::<String>::new());
+        assert_eq!(filters("SELECT id FROM holdings WHERE quantity BETWEEN 1 AND 2"), ["quantity"]);
+        assert_eq!(
+            filters("SELECT h.id FROM holdings h JOIN accounts a ON a.id = h.account_id"),
+            ["a.id", "h.account_id"]
+        );
+        assert_eq!(filters("SELECT id FROM holdings JOIN accounts USING (account_id)"), ["account_id"]);
+        assert_eq!(
+            filters("SELECT ticker FROM holdings GROUP BY ticker HAVING sum(quantity) > 1"),
+            ["quantity"]
+        );
+        assert_eq!(
+            filters("SELECT id FROM holdings WHERE id IN (SELECT id FROM t WHERE name LIKE 'A%')"),
+            ["id", "name"]
+        );
+    }
+
+    #[test]
+    fn resolves_order_by_aliases_and_positions() {
+        assert_eq!(filters("SELECT quantity AS q FROM holdings ORDER BY q"), ["quantity"]);
+        assert_eq!(filters("SELECT id, upper(name) FROM accounts ORDER BY 2"), ["name"]);
+        assert_eq!(filters("SELECT * FROM accounts ORDER BY 2"), ["*"]);
+        assert_eq!(filters("SELECT id FROM accounts ORDER BY name DESC"), ["name"]);
+    }
+
+    #[test]
+    fn tells_table_functions_from_tables_of_the_same_name() {
+        let tables = |sql| referenced_tables(sql).unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(tables("SELECT * FROM generate_series(1, 3) g"), Vec::<String>::new());
+        assert_eq!(tables("SELECT * FROM quotes q, quotes(1) f"), ["quotes"]);
+        assert_eq!(tables("SELECT * FROM quotes(1) f JOIN quotes q ON true"), ["quotes"]);
+        assert_eq!(
+            tables("SELECT * FROM market.prices p, market.prices(2) f, unnest(ARRAY[1]) u"),
+            ["market.prices"]
+        );
+    }
+}
@@ -0,0 +1,839 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    path::Path,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::{
+    postgres::{types::Oid, PgColumn},
+    Column, Executor, Postgres, Row, Statement, Transaction,
+};
+use tracing::{info, warn};
+use crate::auth::qualified;
+use crate::error::BridgeError;
+use crate::handlers::guard::{query_references, QueryReferences, Wildcard};
+
+/// Fichier des règles de masquage (`MCP_PG_MASKS_FILE`).
+#[derive(Deserialize)]
+struct MasksFile {
+    #[serde(default)]
+    masks: Vec<MaskRule>,
+}
+
+/// `column` est `table.colonne` (`schema.table.colonne`), ou un nom de colonne
+/// seul, qui vise cette colonne dans toute table et toute colonne de résultat de ce nom.
+#[derive(Deserialize)]
+struct MaskRule {
+    column: String,
+    #[serde(flatten)]
+    mask: Mask,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Mask {
+    /// La colonne est retirée du résultat.
+    Drop,
+    /// Hachage à clé : les valeurs égales se groupent et se joignent encore, sans être lisibles.
+    Hash,
+    /// Nombres arrondis à un multiple de `step`.
+    Round { step: f64 },
+    /// Nombres remplacés par leur tranche, par ex. `10000-50000`.
+    Bucket { bounds: Vec<f64> },
+    /// Texte réduit à ses `keep` derniers caractères, par ex. `***4821`.
+    Partial {
+        #[serde(default = "default_keep")]
+        keep: usize,
+    },
+}
+
+fn default_keep() -> usize {
+    4
+}
+
+impl Mask {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Drop => "drop",
+            Self::Hash => "hash",
+            Self::Round { .. } => "round",
+            Self::Bucket { .. } => "bucket",
+            Self::Partial { .. } => "partial",
+        }
+    }
+
+    fn validate(&self, column: &str) -> Result<(), BridgeError> {
+        let invalid = match self {
+            Self::Round { step } => (*step <= 0.0).then_some("`step` must be positive"),
+            Self::Bucket { bounds } => (bounds.is_empty() || !bounds.is_sorted_by(|a, b| a < b))
+                .then_some("`bounds` must be increasing"),
+            _ => None,
+        };
+        match invalid {
+            Some(reason) => Err(BridgeError::Api(format!("Mask of '{column}': {reason}"))),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&self, value: &Value, key: &[u8]) -> Value {
+        if value.is_null() {
+            return Value::Null;
+        }
+        match self {
+            Self::Drop => Value::Null,
+            Self::Hash => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key");
+                mac.update(cell_text(value).as_bytes());
+                let digest = mac.finalize().into_bytes();
+                Value::String(digest[..8].iter().map(|b| format!("{b:02x}")).collect())
+            }
+            // Autre chose qu'un nombre passerait sans masque : null à la place.
+            Self::Round { step } => match value.as_f64() {
+                Some(n) => number((n / step).round() * step),
+                None => Value::Null,
+            },
+            Self::Bucket { bounds } => match value.as_f64() {
+                Some(n) => Value::String(bucket(n, bounds)),
+                None => Value::Null,
+            },
+            Self::Partial { keep } => {
+                let text = cell_text(value);
+                let len = text.chars().count();
+                // Une valeur courte serait presque entièrement visible : on la masque en entier.
+                let shown: String = match len > keep * 2 {
+                    true => text.chars().skip(len - keep).collect(),
+                    false => String::new(),
+                };
+                Value::String(format!("***{shown}"))
+            }
+        }
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Résultats entiers sans `.0` final.
+fn number(n: f64) -> Value {
+    match n.fract() == 0.0 && n.abs() < 9.0e15 {
+        true => json!(n as i64),
+        false => json!(n),
+    }
+}
+
+fn bucket(n: f64, bounds: &[f64]) -> String {
+    let show = |b: f64| number(b).to_string();
+    match bounds.iter().position(|&b| n < b) {
+        Some(0) => format!("<{}", show(bounds[0])),
+        Some(i) => format!("{}-{}", show(bounds[i - 1]), show(bounds[i])),
+        None => format!(">={}", show(bounds[bounds.len() - 1])),
+    }
+}
+
+/// Une colonne du résultat et la colonne de table dont elle vient, s'il y en a
+/// une : à travers les vues aussi, mais pas à travers les expressions.
+struct ResultColumn {
+    name: String,
+    origin: Option<(String, String)>,
+}
+
+/// Une relation lue par une requête, résolue par `read_relations` : celle nommée
+/// dans la requête (`direct`), ou une relation lue par une vue nommée, à toute profondeur.
+struct ReadRelation {
+    /// Telle qu'écrite dans la requête.
+    name: String,
+    direct: bool,
+    oid: Oid,
+    /// `schema.table`.
+    relation: String,
+    /// Toutes les colonnes d'une relation directe ; pour les autres, celles qu'une vue lit.
+    columns: Vec<String>,
+}
+
+/// Jusqu'où `trace_origin` suit les vues de vues.
+const MAX_VIEW_DEPTH: usize = 16;
+
+/// Règles de masquage appliquées aux pages de requête.
+///
+/// La correspondance utilise l'origine de chaque colonne du résultat selon
+/// PostgreSQL (la `table.colonne` sélectionnée, à travers alias et sous-requêtes).
+/// Les colonnes calculées (`upper(name)`, `sum(quantity)`) n'ont pas d'origine et
+/// ne sont visées que par les règles sur un nom seul, d'après leur nom.
+#[derive(Default)]
+pub struct ColumnMasks {
+    /// Par `schema.table` et colonne.
+    columns: HashMap<(String, String), Mask>,
+    /// Par nom de colonne, quelle que soit la table.
+    names: HashMap<String, Mask>,
+    key: Vec<u8>,
+}
+
+impl ColumnMasks {
+    /// `key` (`MCP_PG_MASK_KEY`) est la clé des hachages ; une clé aléatoire les
+    /// change à chaque redémarrage.
+    pub fn load(path: &Path, key: Option<String>) -> Result<Self, BridgeError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| BridgeError::Api(format!("Cannot read {}: {e}", path.display())))?;
+        Self::parse(&content, &path.display().to_string(), key)
+    }
+
+    fn parse(content: &str, source: &str, key: Option<String>) -> Result<Self, BridgeError> {
+        let file: MasksFile = toml::from_str(content)
+            .map_err(|e| BridgeError::Api(format!("Invalid masks file {source}: {e}")))?;
+
+        let mut masks = Self {
+            key: match key {
+                Some(key) => key.into_bytes(),
+                None => uuid::Uuid::new_v4().as_bytes().to_vec(),
+            },
+            ..Self::default()
+        };
+        for rule in file.masks {
+            rule.mask.validate(&rule.column)?;
+            info!(column = %rule.column, action = rule.mask.name(), "Registered column mask");
+            match rule.column.rsplit_once('.') {
+                Some((table, column)) => {
+                    masks.columns.insert((qualified(table), column.into()), rule.mask)
+                }
+                None => masks.names.insert(rule.column, rule.mask),
+            };
+        }
+        Ok(masks)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty() && self.names.is_empty()
+    }
+
+    fn rule(&self, table: &str, column: &str) -> Option<&Mask> {
+        self.columns
+            .get(&(table.to_string(), column.to_string()))
+            .or_else(|| self.names.get(column))
+    }
+
+    /// Si `sql` peut lire une colonne masquée autrement que comme simple colonne de
+    /// son résultat : dans une expression, avec `*` ou une ligne entière, ou via une
+    /// vue. Dans le doute, oui, comme l'analyse de `guard::query_references`.
+    fn reads_masked(&self, refs: &QueryReferences, relations: &[ReadRelation]) -> bool {
+        if relations
+            .iter()
+            .filter(|r| !r.direct)
+            .any(|r| r.columns.iter().any(|c| self.rule(&r.relation, c).is_some()))
+        {
+            return true;
+        }
+        if refs.columns.iter().any(|(_, column)| self.names.contains_key(column)) {
+            return true;
+        }
+        // Relations de la requête, telles qu'écrites, avec leurs colonnes masquées.
+        let masked: HashMap<&str, Vec<&str>> = relations
+            .iter()
+            .filter(|r| r.direct)
+            .filter_map(|r| {
+                let columns: Vec<&str> = r
+                    .columns
+                    .iter()
+                    .filter(|c| self.rule(&r.relation, c).is_some())
+                    .map(String::as_str)
+                    .collect();
+                (!columns.is_empty()).then_some((r.name.as_str(), columns))
+            })
+            .collect();
+        if masked.is_empty() {
+            return false;
+        }
+        let designates = |qualifier: &str| {
+            refs.aliases
+                .get(qualifier)
+                .is_some_and(|names| names.iter().any(|n| masked.contains_key(n.as_str())))
+        };
+        refs.natural_join
+            || refs.renamed.iter().any(|r| masked.contains_key(r.as_str()))
+            || refs.wildcards.iter().any(|wildcard| match wildcard {
+                Wildcard::From(from) => from.iter().any(|r| masked.contains_key(r.as_str())),
+                Wildcard::Of(qualifier) => designates(qualifier),
+            })
+            || refs.columns.iter().any(|(qualifier, column)| {
+                (qualifier.is_none() && designates(column))
+                    || masked.values().flatten().any(|c| c == column)
+            })
+    }
+
+    /// Si une vue nommée dans la requête lit une colonne masquée.
+    fn view_reads_masked(&self, relations: &[ReadRelation], name: &str) -> bool {
+        relations
+            .iter()
+            .filter(|r| !r.direct && r.name == name)
+            .any(|r| r.columns.iter().any(|c| self.rule(&r.relation, c).is_some()))
+    }
+
+    /// Refuse une requête dont les lignes renvoyées ou leur ordre dépendent d'une
+    /// colonne masquée (`refs.filters`) : un `WHERE quantity BETWEEN 10 AND 20` ou
+    /// un `ORDER BY` révèlerait la valeur que le masque cache. Une référence qui ne
+    /// désigne aucune colonne de relation (alias de sous-requête, CTE, numéro de
+    /// colonne d'un `*`) est refusée si la requête lit une colonne masquée.
+    async fn check_filters(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        origins: &mut HashMap<Oid, Origin>,
+        refs: &QueryReferences,
+        relations: &[ReadRelation],
+    ) -> Result<(), BridgeError> {
+        for (qualifier, column) in &refs.filters {
+            if self.names.contains_key(column) {
+                return Err(masked_filter_error(column));
+            }
+            let candidates: Vec<&ReadRelation> = relations
+                .iter()
+                .filter(|r| r.direct && r.columns.contains(column))
+                .filter(|r| match qualifier {
+                    Some(qualifier) => refs
+                        .aliases
+                        .get(qualifier)
+                        .is_some_and(|names| names.contains(&r.name)),
+                    None => true,
+                })
+                .collect();
+            if candidates.is_empty() {
+                if self.reads_masked(refs, relations) {
+                    let reference = match qualifier {
+                        Some(qualifier) => format!("{qualifier}.{column}"),
+                        None => column.clone(),
+                    };
+                    return Err(BridgeError::Api(format!(
+                        "`{reference}` is used in WHERE, JOIN, HAVING or ORDER BY but cannot be \
+                         traced to a table column, and the query reads masked columns. \
+                         Filter and sort on the table columns directly."
+                    )));
+                }
+                continue;
+            }
+            for relation in candidates {
+                let attnum: i16 = sqlx::query_scalar(
+                    "SELECT attnum FROM pg_attribute \
+                     WHERE attrelid = $1::bigint::oid AND attname = $2 AND NOT attisdropped",
+                )
+                .bind(i64::from(relation.oid.0))
+                .bind(column)
+                .fetch_one(&mut **tx)
+                .await?;
+                match trace_origin(tx, origins, relation.oid, attnum).await? {
+                    Some((table, name)) if self.rule(&table, &name).is_some() => {
+                        return Err(masked_filter_error(&format!("{table}.{name}")));
+                    }
+                    Some(_) => {}
+                    // Une expression de vue peut dériver d'une colonne masquée.
+                    None if self.view_reads_masked(relations, &relation.name) => {
+                        return Err(masked_filter_error(&format!("{}.{column}", relation.relation)));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Masque de chaque colonne du résultat : la règle de sa colonne de table
+    /// d'origine, sinon une règle sur son nom. Une colonne sans origine dans une
+    /// requête qui lit des données masquées peut les porter (`quantity + 0`,
+    /// `row_to_json`) : elle est retirée.
+    fn choose(&self, columns: &[ResultColumn], reads_masked: bool) -> Vec<(String, Mask)> {
+        columns
+            .iter()
+            .filter_map(|column| {
+                let mask = match &column.origin {
+                    Some((table, name)) => self.rule(table, name).or_else(|| self.names.get(&column.name)),
+                    None => self
+                        .names
+                        .get(&column.name)
+                        .or(reads_masked.then_some(&Mask::Drop)),
+                };
+                Some((column.name.clone(), mask?.clone()))
+            })
+            .collect()
+    }
+
+    /// Les masques à appliquer au résultat de `sql`, par nom de colonne, d'après
+    /// `described`, les colonnes de `sql` préparée seule.
+    ///
+    /// Si `sql` n'a pas pu être préparée (par ex. un paramètre dont PostgreSQL ne
+    /// peut déduire le type), les règles sur un nom seul s'appliquent encore aux
+    /// colonnes de `rows`, mais avec des règles `table.colonne` le résultat est
+    /// refusé plutôt que renvoyé sans masque.
+    ///
+    /// Avec `caller_sql` (SQL écrit par l'appelant), une requête qui filtre, joint
+    /// ou trie sur une colonne masquée est refusée (voir `check_filters`) :
+    /// le SQL de l'opérateur, lui, ne laisse pas l'appelant choisir ses prédicats.
+    pub async fn for_query(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        sql: &str,
+        described: Result<&[PgColumn], &sqlx::Error>,
+        rows: &[Value],
+        caller_sql: bool,
+    ) -> Result<Vec<(String, Mask)>, BridgeError> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let refs = query_references(sql)?;
+        let described = match described {
+            Ok(described) => described,
+            Err(e) if self.columns.is_empty() => {
+                if caller_sql
+                    && let Some((_, column)) = refs.filters.iter().find(|(_, c)| self.names.contains_key(c))
+                {
+                    return Err(masked_filter_error(column));
+                }
+                warn!(error = %e, "Could not find the origin of the result columns, masking by name");
+                let columns: Vec<ResultColumn> = rows
+                    .first()
+                    .and_then(Value::as_object)
+                    .map(|row| {
+                        row.keys()
+                            .map(|name| ResultColumn { name: name.clone(), origin: None })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                return Ok(self.choose(&columns, false));
+            }
+            Err(e) => {
+                return Err(BridgeError::Api(format!(
+                    "Cannot check the result columns against the masking rules ({e}). \
+                     Add explicit casts to the parameters, e.g. $1::text."
+                )))
+            }
+        };
+
+        let mut relations = HashMap::new();
+        let mut columns = Vec::with_capacity(described.len());
+        for column in described {
+            let origin = match column.relation_id().zip(column.relation_attribute_no()) {
+                Some((oid, attnum)) => trace_origin(tx, &mut relations, oid, attnum).await?,
+                None => None,
+            };
+            columns.push(ResultColumn { name: column.name().to_string(), origin });
+        }
+        let without_origin = columns.iter().any(|c| c.origin.is_none());
+        if !caller_sql && !without_origin {
+            return Ok(self.choose(&columns, false));
+        }
+        let read = read_relations(tx, &refs).await?;
+        if caller_sql {
+            self.check_filters(tx, &mut relations, &refs, &read).await?;
+        }
+        let reads_masked = without_origin && self.reads_masked(&refs, &read);
+        Ok(self.choose(&columns, reads_masked))
+    }
+
+    /// Masque les cellules de `rows` en place ; les colonnes retirées disparaissent.
+    pub fn apply(&self, masks: &[(String, Mask)], rows: &mut [Value]) {
+        for row in rows.iter_mut().filter_map(Value::as_object_mut) {
+            for (column, mask) in masks {
+                match mask {
+                    Mask::Drop => {
+                        row.shift_remove(column);
+                    }
+                    mask => {
+                        if let Some(cell) = row.get_mut(column) {
+                            *cell = mask.apply(cell, &self.key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn masked_filter_error(column: &str) -> BridgeError {
+    BridgeError::Api(format!(
+        "Column `{column}` is masked: it cannot be used in WHERE, JOIN, HAVING or ORDER BY. \
+         Select it without filtering or sorting on it."
+    ))
+}
+
+/// Une relation rencontrée par `trace_origin`.
+enum Origin {
+    Table { relation: String, columns: HashMap<i16, String> },
+    /// Origine de chaque colonne de la vue, d'après sa définition préparée seule.
+    View(Vec<Option<(Oid, i16)>>),
+}
+
+/// La colonne de table derrière la colonne `attnum` de la relation `oid`, en
+/// suivant les vues (et vues matérialisées) par leur définition. `None` pour une expression.
+async fn trace_origin(
+    tx: &mut Transaction<'_, Postgres>,
+    relations: &mut HashMap<Oid, Origin>,
+    mut oid: Oid,
+    mut attnum: i16,
+) -> Result<Option<(String, String)>, BridgeError> {
+    for _ in 0..MAX_VIEW_DEPTH {
+        let origin = match relations.entry(oid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(load_origin(tx, oid).await?),
+        };
+        match origin {
+            Origin::Table { relation, columns } => {
+                return Ok(columns.get(&attnum).map(|name| (relation.clone(), name.clone())));
+            }
+            Origin::View(columns) => match columns.get(attnum as usize - 1).copied().flatten() {
+                Some((next_oid, next_attnum)) => (oid, attnum) = (next_oid, next_attnum),
+                None => return Ok(None),
+            },
+        }
+    }
+    Ok(None)
+}
+
+async fn load_origin(tx: &mut Transaction<'_, Postgres>, oid: Oid) -> Result<Origin, BridgeError> {
+    let row = sqlx::query(
+        "SELECT n.nspname || '.' || c.relname AS relation, \
+                CASE WHEN c.relkind IN ('v', 'm') THEN pg_get_viewdef(c.oid) END AS definition, \
+                ARRAY(SELECT a.attnum FROM pg_attribute a \
+                      WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped \
+                      ORDER BY a.attnum) AS attnums, \
+                ARRAY(SELECT a.attname::text FROM pg_attribute a \
+                      WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped \
+                      ORDER BY a.attnum) AS names \
+         FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE c.oid = $1::bigint::oid",
+    )
+    .bind(i64::from(oid.0))
+    .fetch_one(&mut **tx)
+    .await?;
+    let definition: Option<String> = row.get("definition");
+    let Some(definition) = definition else {
+        let attnums: Vec<i16> = row.get("attnums");
+        let names: Vec<String> = row.get("names");
+        return Ok(Origin::Table {
+            relation: row.get("relation"),
+            columns: attnums.into_iter().zip(names).collect(),
+        });
+    };
+    // Une définition impossible à préparer laisse toutes les colonnes de la vue sans origine.
+    let columns = match (&mut **tx).prepare(definition.trim_end().trim_end_matches(';')).await {
+        Ok(statement) => statement
+            .columns()
+            .iter()
+            .map(|c| c.relation_id().zip(c.relation_attribute_no()))
+            .collect(),
+        Err(e) => {
+            warn!(error = %e, view = %row.get::<String, _>("relation"), "Could not prepare the view definition");
+            Vec::new()
+        }
+    };
+    Ok(Origin::View(columns))
+}
+
+/// Les relations de `refs` et, pour les vues, les colonnes de tables et de vues
+/// qu'elles lisent à toute profondeur (`pg_depend` de leur règle de réécriture ;
+/// une dépendance de ligne entière compte pour toutes les colonnes).
+async fn read_relations(
+    tx: &mut Transaction<'_, Postgres>,
+    refs: &QueryReferences,
+) -> Result<Vec<ReadRelation>, BridgeError> {
+    let names: Vec<&str> = refs.relations.iter().map(String::as_str).collect();
+    let rows = sqlx::query(
+        "WITH RECURSIVE reads(name, oid, attnum, direct) AS ( \
+             SELECT name, to_regclass(name)::oid, 0::int2, true FROM unnest($1::text[]) AS name \
+           UNION \
+             SELECT r.name, d.refobjid, d.refobjsubid::int2, false \
+             FROM reads r \
+             JOIN pg_rewrite w ON w.ev_class = r.oid \
+             JOIN pg_depend d ON d.classid = 'pg_rewrite'::regclass AND d.objid = w.oid \
+             WHERE d.refclassid = 'pg_class'::regclass AND d.refobjid <> r.oid \
+         ) \
+         SELECT r.name, r.direct, c.oid, n.nspname || '.' || c.relname AS relation, \
+                CASE WHEN r.attnum = 0 \
+                     THEN ARRAY(SELECT a.attname::text FROM pg_attribute a \
+                                WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped) \
+                     ELSE ARRAY(SELECT a.attname::text FROM pg_attribute a \
+                                WHERE a.attrelid = c.oid AND a.attnum = r.attnum) \
+                END AS columns \
+         FROM reads r \
+         JOIN pg_class c ON c.oid = r.oid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace",
+    )
+    .bind(names)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| ReadRelation {
+            name: row.get("name"),
+            direct: row.get("direct"),
+            oid: row.get("oid"),
+            relation: row.get("relation"),
+            columns: row.get("columns"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masks() -> ColumnMasks {
+        ColumnMasks::parse(
+            r#"
+            [[masks]]
+            column = "holdings.quantity"
+            action = "round"
+            step = 10
+
+            [[masks]]
+            column = "accounts.name"
+            action = "hash"
+
+            [[masks]]
+            column = "iban"
+            action = "drop"
+            "#,
+            "test",
+            Some("key".into()),
+        )
+        .unwrap()
+    }
+
+    fn direct(name: &str, relation: &str, columns: &[&str]) -> ReadRelation {
+        ReadRelation {
+            name: name.into(),
+            direct: true,
+            oid: Oid(0),
+            relation: relation.into(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn through_view(name: &str, relation: &str, columns: &[&str]) -> ReadRelation {
+        ReadRelation { direct: false, ..direct(name, relation, columns) }
+    }
+
+    fn holdings() -> ReadRelation {
+        direct("holdings", "public.holdings", &["id", "account_id", "ticker", "quantity", "avg_price"])
+    }
+
+    /// `view_portfolio_summary` et ce qu'elle lit de `holdings` et `accounts`.
+    fn summary() -> Vec<ReadRelation> {
+        vec![
+            direct("view_portfolio_summary", "public.view_portfolio_summary", &["account_name", "quantity", "current_value"]),
+            through_view("view_portfolio_summary", "public.holdings", &["account_id", "quantity"]),
+            through_view("view_portfolio_summary", "public.accounts", &["id", "name"]),
+        ]
+    }
+
+    fn reads_masked(sql: &str, relations: &[ReadRelation]) -> bool {
+        masks().reads_masked(&query_references(sql).unwrap(), relations)
+    }
+
+    fn column(name: &str, origin: Option<(&str, &str)>) -> ResultColumn {
+        ResultColumn { name: name.into(), origin: origin.map(|(t, c)| (t.into(), c.into())) }
+    }
+
+    fn chosen(columns: &[ResultColumn], reads_masked: bool) -> Vec<(String, &'static str)> {
+        masks()
+            .choose(columns, reads_masked)
+            .into_iter()
+            .map(|(name, mask)| (name, mask.name()))
+            .collect()
+    }
+
+    #[test]
+    fn masks_values() {
+        let key = b"key";
+        assert_eq!(Mask::Round { step: 10.0 }.apply(&json!(137), key), json!(140));
+        assert_eq!(Mask::Round { step: 0.5 }.apply(&json!(1.3), key), json!(1.5));
+        assert_eq!(Mask::Round { step: 10.0 }.apply(&json!("137"), key), Value::Null);
+        let bucket = Mask::Bucket { bounds: vec![10000.0, 50000.0] };
+        assert_eq!(bucket.apply(&json!(500), key), json!("<10000"));
+        assert_eq!(bucket.apply(&json!(10000), key), json!("10000-50000"));
+        assert_eq!(bucket.apply(&json!(75000.5), key), json!(">=50000"));
+        assert_eq!(Mask::Partial { keep: 4 }.apply(&json!("FR7630001007941234567890185"), key), json!("***0185"));
+        assert_eq!(Mask::Partial { keep: 4 }.apply(&json!("12345678"), key), json!("***"));
+        assert_eq!(Mask::Hash.apply(&Value::Null, key), Value::Null);
+    }
+
+    #[test]
+    fn hashes_with_the_key() {
+        let hash = |value: &Value, key: &[u8]| Mask::Hash.apply(value, key);
+        assert_eq!(hash(&json!("Alice"), b"key"), hash(&json!("Alice"), b"key"));
+        assert_ne!(hash(&json!("Alice"), b"key"), hash(&json!("Bob"), b"key"));
+        assert_ne!(hash(&json!("Alice"), b"key"), hash(&json!("Alice"), b"other"));
+        assert_eq!(hash(&json!(42), b"key").as_str().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let parse = |rule: &str| ColumnMasks::parse(&format!("[[masks]]\n{rule}"), "test", None);
+        assert!(parse("column = \"a.b\"\naction = \"round\"\nstep = 0").is_err());
+        assert!(parse("column = \"a.b\"\naction = \"bucket\"\nbounds = [2, 1]").is_err());
+        assert!(parse("column = \"a.b\"\naction = \"blur\"").is_err());
+        assert!(parse("column = \"a.b\"\naction = \"partial\"").is_ok());
+    }
+
+    #[test]
+    fn applies_and_drops() {
+        let masks = masks();
+        let mut rows = vec![json!({ "name": "Alice", "quantity": 137, "iban": "FR76..." })];
+        let chosen = masks.choose(
+            &[
+                column("name", Some(("public.accounts", "name"))),
+                column("quantity", Some(("public.holdings", "quantity"))),
+                column("iban", Some(("public.accounts", "iban"))),
+            ],
+            false,
+        );
+        masks.apply(&chosen, &mut rows);
+        let row = rows[0].as_object().unwrap();
+        assert_eq!(row["quantity"], json!(140));
+        assert_eq!(row["name"].as_str().unwrap().len(), 16);
+        assert!(!row.contains_key("iban"));
+    }
+
+    #[test]
+    fn masks_by_origin_whatever_the_alias() {
+        let columns = [
+            column("q", Some(("public.holdings", "quantity"))),
+            column("ticker", Some(("public.holdings", "ticker"))),
+        ];
+        assert_eq!(chosen(&columns, true), [("q".to_string(), "round")]);
+    }
+
+    #[test]
+    fn drops_expressions_over_masked_columns() {
+        for sql in [
+            "SELECT quantity + 0 AS q, ticker FROM holdings",
+            "SELECT quantity::text AS q, ticker FROM holdings",
+            "SELECT sum(h.quantity) AS q FROM holdings h",
+            "SELECT ticker FROM holdings WHERE quantity > 100",
+            "SELECT to_json(h) AS q FROM holdings h",
+            "SELECT h.* FROM holdings h",
+        ] {
+            assert!(reads_masked(sql, &[holdings()]), "`{sql}`");
+        }
+        let columns = [column("q", None), column("ticker", Some(("public.holdings", "ticker")))];
+        assert_eq!(chosen(&columns, true), [("q".to_string(), "drop")]);
+    }
+
+    #[test]
+    fn keeps_expressions_over_other_columns() {
+        for sql in [
+            "SELECT upper(ticker) AS t, count(*) AS n FROM holdings GROUP BY ticker",
+            "SELECT avg_price * 2 AS p FROM holdings",
+        ] {
+            assert!(!reads_masked(sql, &[holdings()]), "`{sql}`");
+        }
+        assert!(chosen(&[column("n", None)], false).is_empty());
+    }
+
+    #[test]
+    fn drops_view_columns_computed_from_masked_columns() {
+        for sql in [
+            "SELECT current_value FROM view_portfolio_summary",
+            "SELECT count(*) AS n FROM view_portfolio_summary",
+        ] {
+            assert!(reads_masked(sql, &summary()), "`{sql}`");
+        }
+        // Les colonnes suivies à travers la vue gardent le masque de leur colonne de table.
+        let columns = [
+            column("account_name", Some(("public.accounts", "name"))),
+            column("quantity", Some(("public.holdings", "quantity"))),
+            column("current_value", None),
+        ];
+        assert_eq!(
+            chosen(&columns, true),
+            [
+                ("account_name".to_string(), "hash"),
+                ("quantity".to_string(), "round"),
+                ("current_value".to_string(), "drop"),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_nested_json_of_masked_rows() {
+        let sql = "SELECT row_to_json(q) AS r FROM (SELECT * FROM view_portfolio_summary) q";
+        assert!(reads_masked(sql, &summary()));
+        assert!(reads_masked("SELECT json_agg(h) AS r FROM holdings h", &[holdings()]));
+        assert_eq!(chosen(&[column("r", None)], true), [("r".to_string(), "drop")]);
+    }
+
+    #[test]
+    fn treats_bare_names_as_masked_everywhere() {
+        let quotes = direct("quotes", "public.quotes", &["isin", "close"]);
+        assert!(reads_masked("SELECT upper(iban) AS x FROM quotes", &[quotes]));
+        assert_eq!(chosen(&[column("iban", None)], false), [("iban".to_string(), "drop")]);
+    }
+
+    /// Demande une base : `MCP_PG_DATABASE_URL=... cargo test`. Ignoré sinon.
+    #[tokio::test]
+    async fn refuses_filters_on_masked_columns() {
+        let Ok(url) = std::env::var("MCP_PG_DATABASE_URL") else { return };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        let table = format!("mcp_masks_{}", uuid::Uuid::new_v4().simple());
+        let setup = format!(
+            "CREATE TABLE {table} (id int, account text, quantity int, name text); \
+             CREATE VIEW {table}_v AS SELECT id, quantity AS q, quantity * 2 AS double FROM {table}"
+        );
+        pool.execute(sqlx::raw_sql(&setup)).await.unwrap();
+        let masks = ColumnMasks::parse(
+            &format!(
+                "[[masks]]\ncolumn = \"{table}.quantity\"\naction = \"round\"\nstep = 10\n\
+                 [[masks]]\ncolumn = \"{table}.name\"\naction = \"hash\""
+            ),
+            "test",
+            Some("key".into()),
+        )
+        .unwrap();
+
+        let mut tx = pool.begin().await.unwrap();
+        let mut for_query = async |sql: &str, caller_sql: bool| {
+            let described = (&mut *tx).prepare(sql).await.map(|s| s.columns().to_vec());
+            masks
+                .for_query(&mut tx, sql, described.as_deref(), &[], caller_sql)
+                .await
+                .map(|masks| masks.into_iter().map(|(c, m)| (c, m.name())).collect::<Vec<_>>())
+        };
+        for sql in [
+            format!("SELECT id FROM {table} WHERE quantity BETWEEN 10 AND 20"),
+            format!("SELECT id FROM {table} WHERE name LIKE 'A%'"),
+            format!("SELECT id, quantity FROM {table} ORDER BY quantity"),
+            format!("SELECT quantity AS x FROM {table} ORDER BY x"),
+            format!("SELECT id, quantity FROM {table} ORDER BY 2"),
+            format!("SELECT a.id FROM {table} a JOIN {table} b ON a.quantity = b.id"),
+            format!("SELECT account FROM {table} GROUP BY account HAVING sum(quantity) > 100"),
+            format!("SELECT id FROM {table} WHERE id IN (SELECT id FROM {table} WHERE quantity > 5)"),
+            format!("SELECT s.id FROM (SELECT id, quantity AS x FROM {table}) s WHERE s.x > 5"),
+            format!("SELECT id FROM {table}_v WHERE q > 1"),
+            format!("SELECT id FROM {table}_v ORDER BY double"),
+        ] {
+            let error = for_query(&sql, true).await.expect_err(&sql).to_string();
+            assert!(error.contains("masked"), "`{sql}`: {error}");
+        }
+        assert_eq!(
+            for_query(&format!("SELECT id, quantity, name FROM {table} WHERE id > 1 ORDER BY id"), true)
+                .await
+                .unwrap(),
+            [("quantity".to_string(), "round"), ("name".to_string(), "hash")]
+        );
+        assert!(for_query(&format!("SELECT id FROM {table}_v WHERE id > 1"), true).await.unwrap().is_empty());
+        assert!(for_query(&format!("SELECT id FROM {table} WHERE account = 'x'"), true).await.unwrap().is_empty());
+        // Le SQL de l'opérateur ne laisse pas l'appelant choisir ses prédicats.
+        assert_eq!(
+            for_query(&format!("SELECT id, quantity FROM {table} ORDER BY quantity"), false)
+                .await
+                .unwrap(),
+            [("quantity".to_string(), "round")]
+        );
+        tx.rollback().await.unwrap();
+
+        let teardown = format!("DROP VIEW {table}_v; DROP TABLE {table}");
+        pool.execute(sqlx::raw_sql(&teardown)).await.unwrap();
+    }
+}
@@ -1 +1,9 @@
+pub mod format;
+pub mod guard;
+pub mod masking;
+pub mod params;
+pub mod prompts;
 pub mod queries;
+pub mod resources;
+pub mod sql_tools;
+pub mod tools;
@@ -0,0 +1,199 @@
+use std::ops::ControlFlow;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use sqlparser::ast::{visit_expressions, Expr, Value as SqlValue};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::encode::{Encode, IsNull};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{types::Oid, PgArgumentBuffer, PgArguments, PgTypeInfo, Postgres};
+use sqlx::query::QueryScalar;
+use crate::error::BridgeError;
+
+pub type JsonQuery<'q> = QueryScalar<'q, Postgres, Value, PgArguments>;
+
+/// Lie un argument JSON au `$n` suivant, avec un type Postgres déduit de la
+/// valeur JSON :
+///
+/// * `null` → NULL sans type, déduit par PostgreSQL de l'usage du paramètre
+///   (`date > $1` en fait une date), `true`/`false` → boolean
+/// * entiers → bigint, autres nombres → double precision
+/// * `"2024-01-31"` → date, `"2024-01-31T09:30:00"` → timestamp,
+///   `"2024-01-31T09:30:00Z"` (RFC 3339) → timestamptz, autres chaînes → text
+/// * tableaux de chaînes, d'entiers, de nombres ou de booléens → le type tableau
+///   correspondant (avec `= ANY($n)`) ; le reste (objets, tableaux mixtes) → jsonb
+pub fn bind_json<'q>(query: JsonQuery<'q>, value: &Value) -> JsonQuery<'q> {
+    match value {
+        Value::Null => query.bind(UntypedNull),
+        Value::Bool(b) => query.bind(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => query.bind(i),
+            None => query.bind(n.as_f64()),
+        },
+        Value::String(s) => bind_string(query, s),
+        Value::Array(items) => bind_array(query, items, value),
+        other => query.bind(other.clone()),
+    }
+}
+
+fn bind_string<'q>(query: JsonQuery<'q>, s: &str) -> JsonQuery<'q> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        query.bind(date)
+    } else if let Ok(ts) = DateTime::<FixedOffset>::parse_from_rfc3339(s) {
+        query.bind(ts)
+    } else if let Ok(ts) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f") {
+        query.bind(ts)
+    } else {
+        query.bind(s.to_string())
+    }
+}
+
+/// Lie un tableau homogène comme tableau Postgres, sinon toute la valeur en
+/// jsonb quand les éléments n'ont pas un même type scalaire.
+fn bind_array<'q>(query: JsonQuery<'q>, items: &[Value], value: &Value) -> JsonQuery<'q> {
+    if let Some(v) = items.iter().map(Value::as_str).collect::<Option<Vec<_>>>() {
+        query.bind(v.into_iter().map(String::from).collect::<Vec<_>>())
+    } else if let Some(v) = items.iter().map(Value::as_i64).collect::<Option<Vec<_>>>() {
+        query.bind(v)
+    } else if let Some(v) = items.iter().map(Value::as_f64).collect::<Option<Vec<_>>>() {
+        query.bind(v)
+    } else if let Some(v) = items.iter().map(Value::as_bool).collect::<Option<Vec<_>>>() {
+        query.bind(v)
+    } else {
+        query.bind(value.clone())
+    }
+}
+
+/// NULL sans type : l'OID 0 laisse PostgreSQL déduire le type du paramètre.
+struct UntypedNull;
+
+impl sqlx::Type<Postgres> for UntypedNull {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+impl Encode<'_, Postgres> for UntypedNull {
+    fn encode_by_ref(&self, _buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        Ok(IsNull::Yes)
+    }
+}
+
+/// Plus grand paramètre `$n` de `sql`, `0` s'il n'y en a aucun.
+pub fn placeholder_count(sql: &str) -> Result<usize, BridgeError> {
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .map_err(|e| BridgeError::InvalidParams(format!("Could not parse the query ({e})")))?;
+    let mut highest = 0;
+    let _ = visit_expressions(&statements, |expr| {
+        if let Expr::Value(SqlValue::Placeholder(placeholder)) = expr
+            && let Some(n) = placeholder.strip_prefix('$').and_then(|n| n.parse().ok())
+        {
+            highest = highest.max(n);
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    Ok(highest)
+}
+
+/// Refuse une liste de paramètres qui ne remplit pas exactement `$1..$n`, avant
+/// que PostgreSQL ne réponde par une erreur de liaison moins claire.
+pub fn check_param_count(sql: &str, params: &[Value]) -> Result<(), BridgeError> {
+    let expected = placeholder_count(sql)?;
+    match params.len() {
+        given if given == expected => Ok(()),
+        given if expected == 0 => Err(BridgeError::InvalidParams(format!(
+            "The query has no $n placeholder but {given} params were given"
+        ))),
+        given => Err(BridgeError::InvalidParams(format!(
+            "The query uses $1..${expected}, so `params` needs {expected} values, got {given}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn counts_the_highest_placeholder() {
+        assert_eq!(placeholder_count("SELECT 1").unwrap(), 0);
+        assert_eq!(placeholder_count("SELECT $1").unwrap(), 1);
+        assert_eq!(placeholder_count("SELECT $2 + $1").unwrap(), 2);
+        // `$10` n'est pas `$1` suivi d'un zéro.
+        assert_eq!(placeholder_count("SELECT $10, $1").unwrap(), 10);
+        assert_eq!(placeholder_count("SELECT $1 FROM quotes WHERE close > $10").unwrap(), 10);
+    }
+
+    #[test]
+    fn ignores_placeholders_in_literals_and_comments() {
+        assert_eq!(placeholder_count("SELECT '$3' AS s, $1").unwrap(), 1);
+        assert_eq!(placeholder_count("SELECT $$ $7 $$ AS s, $1").unwrap(), 1);
+        assert_eq!(placeholder_count("SELECT \"$5\" FROM quotes").unwrap(), 0);
+        assert_eq!(placeholder_count("SELECT $1 -- AND close > $5").unwrap(), 1);
+        assert_eq!(placeholder_count("SELECT /* $4 */ $2, $1").unwrap(), 2);
+    }
+
+    #[test]
+    fn reports_unparsable_queries_as_invalid_params() {
+        assert!(matches!(placeholder_count("SELECT FROM WHERE"), Err(BridgeError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn checks_the_param_count() {
+        assert!(check_param_count("SELECT 1", &[]).is_ok());
+        assert!(check_param_count("SELECT $1, $2", &[json!(1), json!("a")]).is_ok());
+        let error = |sql: &str, params: &[Value]| match check_param_count(sql, params) {
+            Err(BridgeError::InvalidParams(message)) => message,
+            other => panic!("`{sql}`: {other:?}"),
+        };
+        assert!(error("SELECT 1", &[json!(1)]).contains("no $n placeholder but 1 params"));
+        assert!(error("SELECT $1, $2", &[json!(1)]).contains("needs 2 values, got 1"));
+        assert!(error("SELECT $1", &[json!(1), json!(2)]).contains("needs 1 values, got 2"));
+        // `$1` non utilisé compte quand même : les valeurs sont liées par position.
+        assert!(error("SELECT $2", &[json!(1)]).contains("needs 2 values, got 1"));
+    }
+
+    #[test]
+    fn binds_null_without_a_type() {
+        assert_eq!(<UntypedNull as sqlx::Type<Postgres>>::type_info().oid(), Some(Oid(0)));
+    }
+
+    /// Demande une base : `MCP_PG_DATABASE_URL=... cargo test`. Ignoré sinon.
+    #[tokio::test]
+    async fn infers_the_postgres_type_of_json_values() {
+        let Ok(url) = std::env::var("MCP_PG_DATABASE_URL") else { return };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        let bound = async |sql: &str, value: Value| {
+            // Non préparée d'avance : le cache de sqlx garderait les types du premier appel.
+            let query = sqlx::query_scalar::<_, Value>(sql).persistent(false);
+            let result = bind_json(query, &value).fetch_one(&pool).await;
+            result.unwrap_or_else(|e| panic!("{value}: {e}"))
+        };
+        for (value, expected) in [
+            (json!(true), "boolean"),
+            (json!(42), "bigint"),
+            (json!(1.5), "double precision"),
+            (json!("2024-01-31"), "date"),
+            (json!("2024-01-31T09:30:00"), "timestamp without time zone"),
+            (json!("2024-01-31T09:30:00.250"), "timestamp without time zone"),
+            (json!("2024-01-31T09:30:00Z"), "timestamp with time zone"),
+            (json!("2024-01-31T09:30:00+02:00"), "timestamp with time zone"),
+            (json!("2024-13-01"), "text"),
+            (json!("AAPL"), "text"),
+            (json!(["AAPL", "TTE"]), "text[]"),
+            (json!([1, 2]), "bigint[]"),
+            (json!([1, 2.5]), "double precision[]"),
+            (json!([true, false]), "boolean[]"),
+            (json!([1, "a"]), "jsonb"),
+            (json!([[1], [2]]), "jsonb"),
+            (json!({ "ticker": "AAPL" }), "jsonb"),
+        ] {
+            let sql = "SELECT to_jsonb(pg_typeof($1)::text)";
+            assert_eq!(bound(sql, value.clone()).await, json!(expected), "{value}");
+        }
+        // Sans type, `null` prend celui de son usage : une date ici, là où un text échouerait.
+        let sql = "SELECT jsonb_build_array(CURRENT_DATE > $1)";
+        assert_eq!(bound(sql, Value::Null).await, json!([null]));
+    }
+}
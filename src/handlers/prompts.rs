@@ -0,0 +1,142 @@
+use serde_json::{json, Value};
+use crate::error::BridgeError;
+
+struct PromptArgument {
+    name: &'static str,
+    description: &'static str,
+    required: bool,
+    default: Option<&'static str>,
+}
+
+/// Modèle de prompt : les `{argument}` du texte sont remplacés par les valeurs reçues.
+struct Prompt {
+    name: &'static str,
+    description: &'static str,
+    arguments: &'static [PromptArgument],
+    template: &'static str,
+}
+
+const PROMPTS: &[Prompt] = &[
+    Prompt {
+        name: "weekly_portfolio_review",
+        description: "Weekly review of the whole portfolio: performance, allocation, risks and news.",
+        arguments: &[],
+        template: concat!(
+            "Run a weekly review of my investment portfolio.\n\n",
+            "1. Call `portfolio_performance` for current positions, value and unrealized P&L.\n",
+            "2. Call `sector_exposure` for the sector allocation.\n",
+            "3. Call `at_risk_positions` (default threshold) for drawdowns and negative sentiment.\n",
+            "4. Use `sql_read_query` for the value evolution over the last 7 days:\n",
+            "   SELECT acc.name, s.snapshot_date, s.total_value, s.daily_pnl, s.daily_pnl_pct\n",
+            "   FROM portfolio_snapshots s JOIN accounts acc ON s.account_id = acc.id\n",
+            "   WHERE s.snapshot_date >= CURRENT_DATE - 7 ORDER BY acc.name, s.snapshot_date;\n",
+            "5. Use `sql_read_query` for this week's alerts:\n",
+            "   SELECT ticker, alert_type, message, triggered_at FROM alerts_log\n",
+            "   WHERE triggered_at >= NOW() - INTERVAL '7 days' ORDER BY triggered_at DESC;\n\n",
+            "Summarize: total value and weekly change per account, best and worst positions, ",
+            "concentration issues, positions at risk and notable alerts. ",
+            "End with at most three concrete points to watch next week."
+        ),
+    },
+    Prompt {
+        name: "explain_position",
+        description: "Explain one position: cost basis, price history, technical signals, news and analyst view.",
+        arguments: &[PromptArgument {
+            name: "asset",
+            description: "Ticker or ISIN of the position, e.g. 'AAPL' or 'US0378331005'",
+            required: true,
+            default: None,
+        }],
+        template: concat!(
+            "Explain my position in {asset}.\n\n",
+            "Schema hints: holdings and quotes are linked via `isin`, not `ticker`; ",
+            "signals, news, analyst_ratings and corporate_events are keyed by `ticker`. ",
+            "Never write the identifiers into the SQL text: use the $1 placeholder and pass ",
+            "the value in `params`.\n\n",
+            "Resolve both identifiers first, calling `sql_read_query` with\n",
+            "   sql: SELECT isin, ticker, name, sector, currency FROM assets WHERE ticker = $1 OR isin = $1\n",
+            "   params: a one-element array holding the asset above, exactly as given\n\n",
+            "Then, with `sql_read_query` and params [<isin>] or [<ticker>] from that result:\n",
+            "1. Holdings per account: SELECT acc.name, h.quantity, h.avg_price, h.currency\n",
+            "   FROM holdings h JOIN accounts acc ON h.account_id = acc.id WHERE h.isin = $1;\n",
+            "2. Price history (last 90 days): SELECT date, close, volume FROM quotes\n",
+            "   WHERE isin = $1 AND date >= CURRENT_DATE - 90 ORDER BY date;\n",
+            "3. Latest technical signals: SELECT * FROM signals WHERE ticker = $1\n",
+            "   ORDER BY date DESC LIMIT 1;\n",
+            "4. Recent news: SELECT date, title, sentiment_score FROM news WHERE ticker = $1\n",
+            "   ORDER BY date DESC LIMIT 10;\n",
+            "5. Analyst consensus and upcoming events from analyst_ratings and corporate_events.\n\n",
+            "Explain the unrealized P&L against the cost basis, the trend (price vs SMA50/SMA200, RSI), ",
+            "the news sentiment and the analyst target. Say clearly when data is missing."
+        ),
+    },
+    Prompt {
+        name: "rebalancing_check",
+        description: "Compare the current sector allocation with a target allocation and suggest rebalancing moves.",
+        arguments: &[
+            PromptArgument {
+                name: "target_allocation",
+                description: "Target allocation by sector, e.g. 'Technology 30%, Finance 20%, Energy 10%, other 40%'",
+                required: true,
+                default: None,
+            },
+            PromptArgument {
+                name: "tolerance",
+                description: "Allowed deviation in percentage points before suggesting a move (default: 5)",
+                required: false,
+                default: Some("5"),
+            },
+        ],
+        template: concat!(
+            "Check my portfolio against this target allocation: {target_allocation}.\n\n",
+            "1. Call `sector_exposure` for the current allocation (value and percentage per sector).\n",
+            "2. Call `portfolio_performance` to see which positions make up each sector.\n\n",
+            "For each sector, compare current and target percentages. Flag every sector whose ",
+            "deviation exceeds {tolerance} percentage points and compute the amount to buy or sell ",
+            "to get back on target, based on the total portfolio value. Prefer trimming positions ",
+            "with gains and topping up existing positions over opening new ones. ",
+            "Present the result as a table: sector, current %, target %, deviation, suggested move."
+        ),
+    },
+];
+
+/// `prompts/list`
+pub fn list_prompts() -> Value {
+    let prompts: Vec<Value> = PROMPTS
+        .iter()
+        .map(|p| {
+            let arguments: Vec<Value> = p
+                .arguments
+                .iter()
+                .map(|a| json!({ "name": a.name, "description": a.description, "required": a.required }))
+                .collect();
+            json!({ "name": p.name, "description": p.description, "arguments": arguments })
+        })
+        .collect();
+    json!({ "prompts": prompts })
+}
+
+/// `prompts/get`
+pub fn get_prompt(name: &str, args: Option<&Value>) -> Result<Value, BridgeError> {
+    let prompt = PROMPTS
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| BridgeError::InvalidParams(format!("Unknown prompt: {name}")))?;
+
+    let mut text = prompt.template.to_string();
+    for arg in prompt.arguments {
+        let value = args
+            .and_then(|a| a.get(arg.name)?.as_str())
+            .filter(|v| !v.trim().is_empty())
+            .or(arg.default)
+            .ok_or_else(|| {
+                BridgeError::InvalidParams(format!("Missing required argument '{}'", arg.name))
+            })?;
+        text = text.replace(&format!("{{{}}}", arg.name), value);
+    }
+
+    Ok(json!({
+        "description": prompt.description,
+        "messages": [{ "role": "user", "content": { "type": "text", "text": text } }]
+    }))
+}
@@ -1,103 +1,179 @@
 use crate::state::AppState;
+use crate::auth::{qualified, unqualified};
 use crate::error::BridgeError;
-use tracing::instrument;
-use sqlx::Row;
+use std::sync::Arc;
+use crate::cursors::{Cursor, CursorOwner, HeldResult};
+use crate::handlers::format::{fit_cells, fit_rows, truncate_cells, CellTruncation, OutputFormat};
+use crate::handlers::params::{bind_json, check_param_count};
+use crate::handlers::guard::{referenced_tables, validate_read_only};
+use crate::handlers::masking::Mask;
+use crate::registry::{CallContext, ToolOutput};
+use crate::state::BYTES_PER_TOKEN;
+use crate::progress::PROGRESS_INTERVAL;
+use futures::{stream::BoxStream, TryStreamExt};
+use tokio::time::Instant;
+use tracing::{instrument, warn};
+use serde::Serialize;
+use uuid::Uuid;
+use sqlx::{postgres::PgColumn, Column, Executor, Row, Statement, TypeInfo};
 
 const MAX_ROWS: usize = 500;
 
-fn is_read_only(sql: &str) -> bool {
-    let normalized = sql.trim().to_uppercase();
-    normalized.starts_with("SELECT") || normalized.starts_with("WITH")
-}
-
 // ─────────────────────────────────────────
 // Outil générique
 // ─────────────────────────────────────────
 
-#[instrument(skip(state), fields(sql = %sql))]
-pub async fn sql_read_query(state: &AppState, sql: &str) -> Result<String, BridgeError> {
+#[instrument(skip(state, params), fields(sql = %sql, params = params.len()))]
+pub async fn sql_read_query(
+    state: &AppState,
+    ctx: &CallContext,
+    sql: &str,
+    params: &[serde_json::Value],
+    format: OutputFormat,
+) -> Result<ToolOutput, BridgeError> {
     if sql.is_empty() {
-        return Ok("Query is empty".into());
+        return Err(BridgeError::InvalidParams("Query is empty".into()));
     }
-    if !is_read_only(sql) {
-        return Err(BridgeError::Api(
-            "Read-only mode: only SELECT and WITH...SELECT queries are allowed.".into(),
-        ));
+    ctx.progress("Validating query");
+    let sql = validate_read_only(sql)?;
+    let page = fetch_first_page(state, ctx, &sql, params, format, true, Author::Caller).await?;
+    Ok(render_page(ctx, page, format))
+}
+
+/// Refuse les requêtes qui lisent une relation que le jeton de l'appelant n'autorise pas.
+fn check_table_scope(ctx: &CallContext, sql: &str) -> Result<(), BridgeError> {
+    let Some(principal) = ctx.principal.as_ref().filter(|p| p.restricts_tables()) else {
+        return Ok(());
+    };
+    let denied: Vec<String> = referenced_tables(sql)?
+        .into_iter()
+        .filter(|table| !principal.allows_table(table))
+        .collect();
+    if denied.is_empty() {
+        return Ok(());
     }
-    let wrapped = format!("SELECT row_to_json(q) AS r FROM ({sql}) q LIMIT {MAX_ROWS}");
-    let rows: Vec<serde_json::Value> = sqlx::query_scalar::<_, serde_json::Value>(&wrapped)
-        .fetch_all(&state.pool)
-        .await?;
+    Err(BridgeError::Api(format!(
+        "Access denied: this token cannot read {}.",
+        denied.join(", ")
+    )))
+}
 
-    let count = rows.len();
-    let json = serde_json::to_string_pretty(&serde_json::Value::Array(rows))
-        .unwrap_or_else(|_| "[]".into());
+#[instrument(skip(state, ctx))]
+pub async fn list_tables(state: &AppState, ctx: &CallContext) -> Result<ToolOutput, BridgeError> {
+    let result = visible_tables(state, ctx).await?;
+    Ok(ToolOutput {
+        text: serde_json::to_string_pretty(&result).unwrap_or_else(|_| "[]".into()),
+        structured: Some(serde_json::json!({ "tables": result })),
+    })
+}
 
-    Ok(if count == MAX_ROWS {
-        format!("{json}\n\n⚠️ Results truncated to {MAX_ROWS} rows.")
+#[instrument(skip(state, ctx), fields(table = %table))]
+pub async fn describe_table(
+    state: &AppState,
+    ctx: &CallContext,
+    table: &str,
+) -> Result<ToolOutput, BridgeError> {
+    let result = table_columns(state, ctx, table).await?;
+    let text = if result.is_empty() {
+        let relation = qualified(table);
+        let (schema, name) = relation.split_once('.').unwrap_or(("public", table));
+        format!("Table '{name}' not found in {schema} schema.")
     } else {
-        json
-    })
+        serde_json::to_string_pretty(&result).unwrap_or_else(|_| "[]".into())
+    };
+    Ok(ToolOutput { text, structured: Some(serde_json::json!({ "columns": result })) })
 }
 
-#[instrument(skip(state))]
-pub async fn list_tables(state: &AppState) -> Result<String, BridgeError> {
+/// Tables et vues des schémas de la politique (`public` sans politique) :
+/// `{ "table", "type" }`, limitées à celles que le jeton de `ctx` autorise.
+/// Celles de `public` d'abord et sans schéma, les autres en `schema.table`.
+pub(crate) async fn visible_tables(
+    state: &AppState,
+    ctx: &CallContext,
+) -> Result<Vec<serde_json::Value>, BridgeError> {
+    let mut tx = state.caller_tx(ctx).await?;
     let rows = sqlx::query(
-        "SELECT table_name, table_type \
+        "SELECT table_schema || '.' || table_name AS relation, table_type \
          FROM information_schema.tables \
-         WHERE table_schema = 'public' ORDER BY table_name",
+         WHERE table_schema = ANY($1) \
+         ORDER BY table_schema <> 'public', table_schema, table_name",
     )
-    .fetch_all(&state.pool)
+    .bind(state.policy.schemas())
+    .fetch_all(&mut *tx)
     .await?;
+    tx.commit().await?;
 
-    let result: Vec<serde_json::Value> = rows
+    Ok(rows
         .iter()
-        .map(|row| {
-            let name: String = row.try_get("table_name").unwrap_or_default();
+        .filter_map(|row| {
+            let relation: String = row.try_get("relation").unwrap_or_default();
+            if !ctx.allows_table(&relation) || !state.policy.allows_table(&relation) {
+                return None;
+            }
             let ttype: String = row.try_get("table_type").unwrap_or_default();
-            serde_json::json!({ "table": name, "type": ttype })
+            Some(serde_json::json!({ "table": unqualified(&relation), "type": ttype }))
         })
-        .collect();
-
-    Ok(serde_json::to_string_pretty(&result).unwrap_or_else(|_| "[]".into()))
+        .collect())
 }
 
-#[instrument(skip(state), fields(table = %table))]
-pub async fn describe_table(state: &AppState, table: &str) -> Result<String, BridgeError> {
-    if table.is_empty() || !table.chars().all(|c| c.is_alphanumeric() || c == '_') {
-        return Err(BridgeError::Api(format!("Invalid table name: '{table}'")));
+/// Colonnes d'une table (`schema.table`, ou un nom seul dans `public`), vide si
+/// la table n'existe pas, hors des schémas listés par `visible_tables` ou si le
+/// jeton de `ctx` ne l'autorise pas.
+pub(crate) async fn table_columns(
+    state: &AppState,
+    ctx: &CallContext,
+    table: &str,
+) -> Result<Vec<serde_json::Value>, BridgeError> {
+    validate_table_name(table)?;
+    let relation = qualified(table);
+    let (schema, name) = relation.split_once('.').unwrap_or(("public", table));
+    if !state.policy.schemas().iter().any(|s| s == schema)
+        || !ctx.allows_table(table)
+        || !state.policy.allows_table(table)
+    {
+        return Ok(Vec::new());
     }
+    let mut tx = state.caller_tx(ctx).await?;
     let rows = sqlx::query(
         "SELECT column_name, data_type, is_nullable, column_default \
          FROM information_schema.columns \
-         WHERE table_schema = 'public' AND table_name = $1 \
+         WHERE table_schema = $1 AND table_name = $2 \
          ORDER BY ordinal_position",
     )
-    .bind(table)
-    .fetch_all(&state.pool)
+    .bind(schema)
+    .bind(name)
+    .fetch_all(&mut *tx)
     .await?;
+    tx.commit().await?;
 
-    if rows.is_empty() {
-        return Ok(format!("Table '{table}' not found in public schema."));
-    }
-
-    let result: Vec<serde_json::Value> = rows
+    Ok(rows
         .iter()
-        .map(|row| {
+        .filter_map(|row| {
             let col: String = row.try_get("column_name").unwrap_or_default();
+            if state.policy.hides_column(table, &col) {
+                return None;
+            }
             let dtype: String = row.try_get("data_type").unwrap_or_default();
             let nullable: String = row.try_get("is_nullable").unwrap_or_default();
             let default: Option<String> = row.try_get("column_default").unwrap_or(None);
-            serde_json::json!({
+            Some(serde_json::json!({
                 "column": col,
                 "type": dtype,
                 "nullable": nullable == "YES",
                 "default": default,
-            })
+            }))
         })
-        .collect();
+        .collect())
+}
 
-    Ok(serde_json::to_string_pretty(&result).unwrap_or_else(|_| "[]".into()))
+/// `table` ou `schema.table`, chaque partie en lettres, chiffres et `_`.
+pub(crate) fn validate_table_name(table: &str) -> Result<(), BridgeError> {
+    let valid =
+        |part: &str| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if table.split('.').count() > 2 || !table.split('.').all(valid) {
+        return Err(BridgeError::Api(format!("Invalid table name: '{table}'")));
+    }
+    Ok(())
 }
 
 // ─────────────────────────────────────────
@@ -106,15 +182,16 @@ pub async fn describe_table(state: &AppState, table: &str) -> Result<String, Bri
 
 /// UC-02 — Performance globale et par compte
 #[instrument(skip(state))]
-pub async fn portfolio_performance(state: &AppState) -> Result<String, BridgeError> {
+pub async fn portfolio_performance(
+    state: &AppState,
+    ctx: &CallContext,
+    format: OutputFormat,
+) -> Result<ToolOutput, BridgeError> {
     let sql = "
-        SELECT row_to_json(q) AS r FROM (
-            SELECT * FROM view_portfolio_summary
-            ORDER BY account_name, pnl_pct ASC
-            LIMIT 500
-        ) q
+        SELECT * FROM view_portfolio_summary
+        ORDER BY account_name, pnl_pct ASC
     ";
-    query_to_json(state, sql).await
+    query_to_json(state, ctx, sql, &[], format).await
 }
 
 /// UC-02 / UC-07 — Positions à risque
@@ -123,8 +200,10 @@ pub async fn portfolio_performance(state: &AppState) -> Result<String, BridgeErr
 #[instrument(skip(state), fields(threshold = %drawdown_threshold))]
 pub async fn at_risk_positions(
     state: &AppState,
+    ctx: &CallContext,
     drawdown_threshold: f64,
-) -> Result<String, BridgeError> {
+    format: OutputFormat,
+) -> Result<ToolOutput, BridgeError> {
     if drawdown_threshold <= 0.0 || drawdown_threshold > 100.0 {
         return Err(BridgeError::Api(
             "drawdown_threshold must be between 0.0 and 100.0".into(),
@@ -181,12 +260,16 @@ pub async fn at_risk_positions(
         ORDER BY drawdown_pct ASC
         "
     );
-    query_to_json(state, &sql).await
+    query_to_json(state, ctx, &sql, &[], format).await
 }
 
 /// UC-03 — Exposition sectorielle et concentration
 #[instrument(skip(state))]
-pub async fn sector_exposure(state: &AppState) -> Result<String, BridgeError> {
+pub async fn sector_exposure(
+    state: &AppState,
+    ctx: &CallContext,
+    format: OutputFormat,
+) -> Result<ToolOutput, BridgeError> {
     let sql = "
         SELECT
             COALESCE(ast.sector, 'Unknown')                      AS sector,
@@ -207,24 +290,600 @@ pub async fn sector_exposure(state: &AppState) -> Result<String, BridgeError> {
         GROUP BY ast.sector
         ORDER BY total_value DESC
     ";
-    query_to_json(state, sql).await
+    query_to_json(state, ctx, sql, &[], format).await
 }
 
 // ─────────────────────────────────────────
 // Helper interne
 // ─────────────────────────────────────────
 
-async fn query_to_json(state: &AppState, sql: &str) -> Result<String, BridgeError> {
-    let wrapped = format!("SELECT row_to_json(q) AS r FROM ({sql}) q LIMIT {MAX_ROWS}");
-    let rows: Vec<serde_json::Value> = sqlx::query_scalar::<_, serde_json::Value>(&wrapped)
-        .fetch_all(&state.pool)
-        .await?;
-    let count = rows.len();
-    let json = serde_json::to_string_pretty(&serde_json::Value::Array(rows))
-        .unwrap_or_else(|_| "[]".into());
-    Ok(if count == MAX_ROWS {
-        format!("{json}\n\n⚠️ Results truncated to {MAX_ROWS} rows.")
-    } else {
-        json
+/// `outputSchema` de tous les outils construits sur `query_to_json`.
+pub(crate) fn query_output_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "columns": {
+                "type": "array",
+                "description": "Result columns in order, with their PostgreSQL type.",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "type": { "type": ["string", "null"] }
+                    },
+                    "required": ["name", "type"]
+                }
+            },
+            "rows": {
+                "type": "array",
+                "description": "One object per row, keyed by column name.",
+                "items": { "type": "object" }
+            },
+            "next_cursor": {
+                "type": ["string", "null"],
+                "description": "Pass as `cursor` to sql_read_query for the next page."
+            },
+            "notes": {
+                "type": "array",
+                "description": "What was cut from this page (long cells, rows over the output budget).",
+                "items": { "type": "string" }
+            }
+        },
+        "required": ["columns", "rows", "next_cursor", "notes"]
     })
 }
+
+/// `params` sont liés dans l'ordre aux placeholders `$1..$n` de `sql`.
+/// Renvoie la première page ; le `next_cursor` du texte permet de continuer.
+pub(crate) async fn query_to_json(
+    state: &AppState,
+    ctx: &CallContext,
+    sql: &str,
+    params: &[serde_json::Value],
+    format: OutputFormat,
+) -> Result<ToolOutput, BridgeError> {
+    let page = fetch_first_page(state, ctx, sql, params, format, true, Author::Operator).await?;
+    Ok(render_page(ctx, page, format))
+}
+
+/// Continue un résultat depuis le jeton `next_cursor` d'une page précédente,
+/// dans le format de cette page.
+pub(crate) async fn continue_cursor(
+    state: &AppState,
+    ctx: &CallContext,
+    token: &str,
+) -> Result<ToolOutput, BridgeError> {
+    let cursor = state
+        .cursors
+        .get(token, &CursorOwner::of(ctx))
+        .ok_or_else(expired_cursor)?;
+    let format = cursor.result.format;
+    let page = fetch_next_page(state, ctx, cursor).await?;
+    Ok(render_page(ctx, page, format))
+}
+
+fn expired_cursor() -> BridgeError {
+    BridgeError::Api("Unknown or expired cursor. Run the query again.".into())
+}
+
+/// Une page de résultat, avant mise en forme.
+struct Page {
+    rows: Vec<serde_json::Value>,
+    /// Chargées seulement pour les clients qui reçoivent `structuredContent`.
+    columns: Option<Vec<serde_json::Value>>,
+    next_cursor: Option<String>,
+    notes: Vec<String>,
+}
+
+/// `structuredContent` d'une page.
+#[derive(Serialize)]
+struct Structured<'a> {
+    columns: &'a [serde_json::Value],
+    rows: &'a [serde_json::Value],
+    next_cursor: Option<&'a str>,
+    notes: &'a [String],
+}
+
+/// Texte d'une page en `format`, notes après les lignes.
+fn page_text(format: OutputFormat, rows: &[serde_json::Value], notes: &[String]) -> String {
+    let mut text = format.render(rows);
+    for note in notes {
+        text.push_str("\n\n");
+        text.push_str(note);
+    }
+    text
+}
+
+/// Met une page en forme en `format`, notes après les lignes.
+fn render_page(ctx: &CallContext, page: Page, format: OutputFormat) -> ToolOutput {
+    let Page { rows, columns, next_cursor, notes } = page;
+    ctx.progress(format!("Serializing {} rows", rows.len()));
+    let text = page_text(format, &rows, &notes);
+    let structured = columns.and_then(|columns| {
+        let next_cursor = next_cursor.as_deref();
+        let structured = Structured { columns: &columns, rows: &rows, next_cursor, notes: &notes };
+        serde_json::to_value(structured).ok()
+    });
+    ToolOutput { text, structured }
+}
+
+/// Seulement les lignes de la première page de `sql`, sans les notes : pour les
+/// corps qui doivent rester du JSON valide.
+pub(crate) async fn query_rows(
+    state: &AppState,
+    ctx: &CallContext,
+    sql: &str,
+) -> Result<Vec<serde_json::Value>, BridgeError> {
+    let page = fetch_first_page(state, ctx, sql, &[], OutputFormat::Json, false, Author::Operator);
+    Ok(page.await?.rows)
+}
+
+/// Qui a écrit le SQL d'une page : l'appelant (`sql_read_query`), ou l'opérateur
+/// (outils métier, outils SQL déclaratifs, ressources).
+#[derive(Clone, Copy, PartialEq)]
+enum Author {
+    Caller,
+    Operator,
+}
+
+/// Exécute la première page de `sql` : un curseur `SCROLL ... WITH HOLD` dont on
+/// lit `MAX_ROWS` lignes, plus une pour savoir s'il faut un `next_cursor`.
+///
+/// La page passe ensuite par l'`OutputBudget` (voir `fit_page`). S'il reste des
+/// lignes et que `paginate` le permet, le `COMMIT` matérialise le curseur, gardé
+/// avec sa connexion dans `state.cursors` : les pages suivantes lisent cet
+/// instantané. Sinon le curseur est fermé avant le `COMMIT`.
+///
+/// Le SQL de l'appelant ne peut ni filtrer ni trier sur une colonne masquée
+/// (voir `ColumnMasks::for_query`).
+async fn fetch_first_page(
+    state: &AppState,
+    ctx: &CallContext,
+    sql: &str,
+    params: &[serde_json::Value],
+    format: OutputFormat,
+    paginate: bool,
+    author: Author,
+) -> Result<Page, BridgeError> {
+    if let Some(trace) = &ctx.query {
+        trace.started(sql);
+    }
+    state.policy.check_query(sql)?;
+    check_table_scope(ctx, sql)?;
+    check_param_count(sql, params)?;
+    let name = HeldResult::cursor_name();
+    // `q.*` et non `q` : une colonne de résultat nommée `q` masquerait la ligne entière.
+    let declare = format!(
+        "DECLARE {name} SCROLL CURSOR WITH HOLD FOR SELECT row_to_json(q.*) AS r FROM ({sql}) q"
+    );
+    // Le nom du curseur rend chaque texte unique : inutile de le garder préparé.
+    let declare = params.iter().fold(
+        sqlx::query_scalar::<_, serde_json::Value>(&declare).persistent(false),
+        bind_json,
+    );
+    let fetch = format!("FETCH FORWARD {} FROM {name}", MAX_ROWS + 1);
+
+    let mut connection = state.pool.acquire().await?;
+    let mut tx = state.tracked_tx(ctx, &mut connection).await?;
+    ctx.progress("Executing query");
+    // `DECLARE` ne renvoie aucune ligne.
+    declare.fetch_optional(&mut **tx).await?;
+    let rows = fetch_rows(ctx, sqlx::query_scalar(&fetch).persistent(false).fetch(&mut **tx)).await?;
+    // Une seule préparation décrit le résultat pour structuredContent et les masques ;
+    // ni l'un ni l'autre n'en a besoin sinon.
+    let structured = ctx.features().structured_output;
+    let described = match structured || !state.masks.is_empty() {
+        true => Some((&mut **tx).prepare(sql).await.map(|s| s.columns().to_vec())),
+        false => None,
+    };
+    let (mut columns, masks) = match &described {
+        Some(described) => (
+            structured.then(|| result_columns(described.as_deref(), &rows)),
+            state
+                .masks
+                .for_query(&mut tx, sql, described.as_deref(), &rows, author == Author::Caller)
+                .await?,
+        ),
+        None => (None, Vec::new()),
+    };
+    if let Some(columns) = &mut columns {
+        mask_columns(columns, &masks);
+    }
+
+    let shape = PageShape { format, columns: columns.as_deref(), offset: 0, paginate };
+    let fitted = fit_page(state, &shape, &masks, rows);
+    let hold = paginate && fitted.more;
+    match hold {
+        true => ctx.progress("Keeping the result for the next pages"),
+        false => {
+            (&mut **tx).execute(sqlx::raw_sql(&format!("CLOSE {name}"))).await?;
+        }
+    }
+    tx.commit().await?;
+
+    let next_cursor = hold.then(|| {
+        let result = HeldResult {
+            name,
+            sql: sql.into(),
+            format,
+            masks: masks.clone(),
+            columns: columns.clone(),
+            owner: CursorOwner::of(ctx),
+            connection: tokio::sync::Mutex::new(Some(connection)),
+        };
+        state.cursors.hold(Arc::new(result), fitted.rows.len())
+    });
+    Ok(finish_page(state, ctx, fitted, &masks, columns, 0, next_cursor))
+}
+
+/// Lit la page d'un résultat conservé à l'offset du curseur. Le résultat est
+/// fermé après sa dernière page, ou si sa connexion échoue.
+async fn fetch_next_page(
+    state: &AppState,
+    ctx: &CallContext,
+    cursor: Cursor,
+) -> Result<Page, BridgeError> {
+    let Cursor { result, offset, .. } = cursor;
+    if let Some(trace) = &ctx.query {
+        trace.started(&result.sql);
+    }
+    // La politique a pu changer depuis la première page.
+    state.policy.check_query(&result.sql)?;
+    check_table_scope(ctx, &result.sql)?;
+
+    let rows = {
+        let mut connection = result.connection.lock().await;
+        let connection = connection.as_mut().ok_or_else(expired_cursor)?;
+        let fetch = format!("FETCH FORWARD {} FROM {}", MAX_ROWS + 1, result.name);
+        let moved = (&mut **connection)
+            .execute(sqlx::raw_sql(&format!("MOVE ABSOLUTE {offset} IN {}", result.name)))
+            .await;
+        match moved {
+            Ok(_) => {
+                let rows = sqlx::query_scalar(&fetch).persistent(false).fetch(&mut **connection);
+                fetch_rows(ctx, rows).await
+            }
+            Err(e) => Err(e.into()),
+        }
+    };
+    let rows = rows.inspect_err(|_| state.cursors.close(&result))?;
+
+    let shape = PageShape {
+        format: result.format,
+        columns: result.columns.as_deref(),
+        offset,
+        paginate: true,
+    };
+    let fitted = fit_page(state, &shape, &result.masks, rows);
+    let next_cursor = match fitted.more {
+        true => Some(state.cursors.next(&result, offset + fitted.rows.len())),
+        false => {
+            state.cursors.close(&result);
+            None
+        }
+    };
+    Ok(finish_page(state, ctx, fitted, &result.masks, result.columns.clone(), offset, next_cursor))
+}
+
+/// Lignes d'une page après masquage et budget de sortie.
+struct Fitted {
+    rows: Vec<serde_json::Value>,
+    /// Lignes lues pour la page, avant le budget.
+    fetched: usize,
+    truncated: CellTruncation,
+    /// Il reste des lignes : au-delà de `MAX_ROWS`, ou laissées par le budget.
+    more: bool,
+}
+
+/// Ce qui accompagne les lignes d'une page, pour mesurer sa taille avant de la finir.
+struct PageShape<'a> {
+    format: OutputFormat,
+    /// Colonnes de `structuredContent`, pour les clients qui le reçoivent.
+    columns: Option<&'a [serde_json::Value]>,
+    offset: usize,
+    /// Un `next_cursor` est donné s'il reste des lignes.
+    paginate: bool,
+}
+
+impl PageShape<'_> {
+    /// Octets de la page : son texte, notes comprises, et la copie `structuredContent`.
+    fn bytes(
+        &self,
+        rows: &[serde_json::Value],
+        notes: &[String],
+        next_cursor: Option<&str>,
+    ) -> usize {
+        let text = page_text(self.format, rows, notes).len();
+        let structured = self.columns.map_or(0, |columns| {
+            let structured = Structured { columns, rows, next_cursor, notes };
+            serde_json::to_vec(&structured).map_or(0, |json| json.len())
+        });
+        text + structured
+    }
+}
+
+/// Masque les lignes puis les fait tenir dans l'`OutputBudget` : les longues
+/// cellules texte sont coupées et les dernières lignes qui dépassent la limite en
+/// octets/jetons, notes et `structuredContent` compris, sont laissées à la page
+/// suivante. Une première ligne trop grande à elle seule voit ses cellules texte
+/// coupées plus court que `MCP_PG_MAX_CELL_CHARS`.
+fn fit_page(
+    state: &AppState,
+    shape: &PageShape,
+    masks: &[(String, Mask)],
+    mut rows: Vec<serde_json::Value>,
+) -> Fitted {
+    // Le masquage d'abord : le budget de sortie mesure ce qui est vraiment renvoyé.
+    state.masks.apply(masks, &mut rows);
+    let more = rows.len() > MAX_ROWS;
+    rows.truncate(MAX_ROWS);
+
+    let budget = &state.budget;
+    let fetched = rows.len();
+    let Some(limit) = budget.byte_limit() else {
+        let truncated = truncate_cells(&mut rows, budget.max_cell_chars);
+        return Fitted { rows, fetched, truncated, more };
+    };
+    // Taille de la page des `count` premières lignes, avec les notes qu'elle aura ;
+    // un faux jeton de la longueur des vrais tient la place de `next_cursor`.
+    let placeholder = Uuid::nil().to_string();
+    let size = |rows: &[serde_json::Value], truncated: &CellTruncation, count: usize| {
+        let next_cursor = (shape.paginate && (more || count < fetched)).then_some(&*placeholder);
+        let notes = page_notes(state, masks, truncated, shape.offset, count, fetched, next_cursor);
+        shape.bytes(&rows[..count], &notes, next_cursor)
+    };
+
+    // Ajuste sur les cellules coupées de toutes les lignes lues, dont les notes
+    // couvrent celles des lignes renvoyées.
+    let mut preview = rows.clone();
+    let cut = truncate_cells(&mut preview, budget.max_cell_chars);
+    let count = fit_rows(fetched, limit, |count| size(&preview, &cut, count));
+    rows.truncate(count);
+    let mut cut_rows = rows.clone();
+    let mut truncated = truncate_cells(&mut cut_rows, budget.max_cell_chars);
+    if count == 1 && size(&cut_rows, &truncated, 1) > limit {
+        let max_chars = fit_cells(&rows, limit, |rows, truncated| size(rows, truncated, 1));
+        cut_rows = rows;
+        truncated = truncate_cells(&mut cut_rows, max_chars);
+    }
+    let more = more || count < fetched;
+    Fitted { rows: cut_rows, fetched, truncated, more }
+}
+
+/// Termine une page commençant à `offset` : ses notes et la trace de l'audit.
+fn finish_page(
+    state: &AppState,
+    ctx: &CallContext,
+    fitted: Fitted,
+    masks: &[(String, Mask)],
+    columns: Option<Vec<serde_json::Value>>,
+    offset: usize,
+    next_cursor: Option<String>,
+) -> Page {
+    let Fitted { rows, fetched, truncated, .. } = fitted;
+    let count = rows.len();
+    let notes =
+        page_notes(state, masks, &truncated, offset, count, fetched, next_cursor.as_deref());
+
+    if let Some(trace) = &ctx.query {
+        trace.finished(count, next_cursor.is_some() || truncated.cells > 0);
+    }
+
+    Page { rows, columns, next_cursor, notes }
+}
+
+/// Notes d'une page de `count` lignes parmi `fetched` lues, commençant à `offset` :
+/// masques, cellules coupées, lignes laissées par le budget et `next_cursor`.
+fn page_notes(
+    state: &AppState,
+    masks: &[(String, Mask)],
+    truncated: &CellTruncation,
+    offset: usize,
+    count: usize,
+    fetched: usize,
+    next_cursor: Option<&str>,
+) -> Vec<String> {
+    let budget = &state.budget;
+    let mut notes = Vec::new();
+    if !masks.is_empty() {
+        let masked: Vec<String> = masks
+            .iter()
+            .map(|(column, mask)| format!("{column} ({})", mask.name()))
+            .collect();
+        notes.push(format!("🔒 Masked column(s): {}.", masked.join(", ")));
+    }
+    if truncated.cells > 0 {
+        notes.push(format!(
+            "✂️ {} cell(s) cut to {} characters in column(s) {} ({} characters elided).",
+            truncated.cells,
+            truncated.max_chars,
+            truncated.columns.iter().cloned().collect::<Vec<_>>().join(", "),
+            truncated.chars,
+        ));
+    }
+    if count < fetched {
+        let limit = budget.byte_limit().unwrap_or_default();
+        notes.push(format!(
+            "⚠️ Output budget of {limit} bytes (~{} tokens) reached: rows {} to {} were \
+             left out of this page.",
+            limit / BYTES_PER_TOKEN,
+            offset + count + 1,
+            offset + fetched,
+        ));
+    }
+    if let Some(token) = next_cursor {
+        notes.push(format!(
+            "⚠️ Rows {} to {} returned, more rows available.\n\
+             next_cursor: {token}\n\
+             Call sql_read_query with {{\"cursor\": \"{token}\"}} for the next page \
+             (valid {} minutes).",
+            offset + 1,
+            offset + count,
+            state.cursors.ttl().as_secs() / 60,
+        ));
+    }
+    notes
+}
+
+/// Colonnes de `structuredContent` après masquage : les colonnes retirées
+/// disparaissent, et les masques qui rendent du texte changent leur type.
+fn mask_columns(columns: &mut Vec<serde_json::Value>, masks: &[(String, Mask)]) {
+    columns.retain_mut(|column| {
+        let name = column.get("name").and_then(serde_json::Value::as_str).unwrap_or_default();
+        match masks.iter().find(|(masked, _)| masked == name) {
+            Some((_, Mask::Drop)) => false,
+            Some((_, Mask::Hash | Mask::Bucket { .. } | Mask::Partial { .. })) => {
+                column["type"] = "TEXT".into();
+                true
+            }
+            _ => true,
+        }
+    });
+}
+
+/// Collecte les lignes d'une page. Avec un jeton de progression, signale toutes
+/// les `PROGRESS_INTERVAL` depuis combien de temps la requête tourne, puis
+/// combien de lignes sont arrivées.
+async fn fetch_rows(
+    ctx: &CallContext,
+    mut stream: BoxStream<'_, Result<serde_json::Value, sqlx::Error>>,
+) -> Result<Vec<serde_json::Value>, BridgeError> {
+    let started = Instant::now();
+    let mut ticker = tokio::time::interval_at(started + PROGRESS_INTERVAL, PROGRESS_INTERVAL);
+    let mut rows = Vec::new();
+    loop {
+        tokio::select! {
+            row = stream.try_next() => match row? {
+                Some(row) => rows.push(row),
+                None => return Ok(rows),
+            },
+            _ = ticker.tick(), if ctx.progress.is_some() => match rows.len() {
+                0 => ctx.progress(format!("Executing query ({}s)", started.elapsed().as_secs())),
+                // Pas de total : la page s'arrête à `MAX_ROWS`, plus une ligne pour voir la suite.
+                n => ctx.progress(format!("Fetched {n} rows")),
+            },
+        }
+    }
+}
+
+/// Noms et types PostgreSQL des colonnes du résultat, d'après la requête
+/// préparée. À défaut, les clés de la première ligne, sans type, si `sql` ne
+/// peut pas être préparée seule (par ex. un paramètre dont Postgres ne peut
+/// déduire le type).
+fn result_columns(
+    described: Result<&[PgColumn], &sqlx::Error>,
+    rows: &[serde_json::Value],
+) -> Vec<serde_json::Value> {
+    match described {
+        Ok(columns) => columns
+            .iter()
+            .map(|c| serde_json::json!({ "name": c.name(), "type": c.type_info().name() }))
+            .collect(),
+        Err(e) => {
+            warn!(error = %e, "Could not describe the result columns");
+            rows.first()
+                .and_then(serde_json::Value::as_object)
+                .map(|row| {
+                    row.keys()
+                        .map(|name| serde_json::json!({ "name": name, "type": null }))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::OutputBudget;
+
+    /// Lignes `id` d'une page CSV et son `next_cursor`.
+    fn page_ids(output: &ToolOutput) -> (Vec<i64>, Option<String>) {
+        let ids = output.text.lines().skip(1).map_while(|l| l.parse().ok()).collect();
+        let token = output
+            .text
+            .lines()
+            .find_map(|l| l.strip_prefix("next_cursor: "))
+            .map(String::from);
+        (ids, token)
+    }
+
+    /// Demande une base : `MCP_PG_DATABASE_URL=... cargo test`. Ignoré sinon.
+    #[tokio::test]
+    async fn pages_through_a_changing_table() {
+        if std::env::var("MCP_PG_DATABASE_URL").is_err() {
+            return;
+        }
+        let state = AppState::init().await;
+        let ctx = CallContext::default();
+        let table = format!("mcp_paging_{}", uuid::Uuid::new_v4().simple());
+        let setup = format!(
+            "CREATE TABLE {table} (id int PRIMARY KEY); \
+             INSERT INTO {table} SELECT g FROM generate_series(1, 1200) g"
+        );
+        state.pool.execute(sqlx::raw_sql(&setup)).await.unwrap();
+
+        let sql = format!("SELECT id FROM {table} ORDER BY id");
+        let first = query_to_json(&state, &ctx, &sql, &[], OutputFormat::Csv).await.unwrap();
+        let (ids, token) = page_ids(&first);
+        assert_eq!(ids, (1..=500).collect::<Vec<_>>());
+
+        // Sous OFFSET, ces lignes décaleraient les pages suivantes.
+        let change = format!(
+            "DELETE FROM {table} WHERE id <= 300; \
+             INSERT INTO {table} SELECT -g FROM generate_series(1, 700) g"
+        );
+        state.pool.execute(sqlx::raw_sql(&change)).await.unwrap();
+
+        let second = continue_cursor(&state, &ctx, &token.unwrap()).await.unwrap();
+        let (ids, token) = page_ids(&second);
+        assert_eq!(ids, (501..=1000).collect::<Vec<_>>());
+
+        state.pool.execute(sqlx::raw_sql(&format!("DELETE FROM {table}"))).await.unwrap();
+        let token = token.unwrap();
+        let third = continue_cursor(&state, &ctx, &token).await.unwrap();
+        let (ids, last) = page_ids(&third);
+        assert_eq!(ids, (1001..=1200).collect::<Vec<_>>());
+        assert_eq!(last, None);
+        // La dernière page a fermé le résultat : ses jetons ne servent plus.
+        assert!(continue_cursor(&state, &ctx, &token).await.is_err());
+
+        state.pool.execute(sqlx::raw_sql(&format!("DROP TABLE {table}"))).await.unwrap();
+    }
+
+    /// Octets envoyés pour une page : le texte et la copie `structuredContent`.
+    fn output_bytes(output: &ToolOutput) -> usize {
+        let structured = output.structured.as_ref().map_or(0, |s| s.to_string().len());
+        output.text.len() + structured
+    }
+
+    /// Demande une base : `MCP_PG_DATABASE_URL=... cargo test`. Ignoré sinon.
+    #[tokio::test]
+    async fn fits_notes_and_structured_content_in_the_budget() {
+        if std::env::var("MCP_PG_DATABASE_URL").is_err() {
+            return;
+        }
+        let mut state = AppState::init().await;
+        state.budget = OutputBudget { max_bytes: 2_000, max_tokens: 0, max_cell_chars: 0 };
+        let ctx = CallContext {
+            protocol_version: Some("2025-06-18".into()),
+            ..CallContext::default()
+        };
+
+        let sql = "SELECT g AS id, repeat('x', 40) AS pad FROM generate_series(1, 200) g";
+        let page = query_to_json(&state, &ctx, sql, &[], OutputFormat::Json).await.unwrap();
+        assert!(output_bytes(&page) <= 2_000, "{} bytes", output_bytes(&page));
+        let structured = page.structured.unwrap();
+        let rows = structured["rows"].as_array().unwrap().len();
+        assert!(rows > 1 && rows < 200);
+        assert!(structured["next_cursor"].is_string());
+
+        // Une ligne seule trop grande : ses cellules sont coupées pour tenir.
+        let sql = "SELECT 1 AS id, repeat('y', 10000) AS body";
+        let page = query_to_json(&state, &ctx, sql, &[], OutputFormat::Json).await.unwrap();
+        assert!(output_bytes(&page) <= 2_000, "{} bytes", output_bytes(&page));
+        assert!(page.text.contains("1 cell(s) cut to"), "{}", page.text);
+        assert!(page.structured.unwrap()["next_cursor"].is_null());
+    }
+}
@@ -0,0 +1,129 @@
+use serde_json::{json, Value};
+use tracing::instrument;
+use crate::error::BridgeError;
+use crate::auth::qualified;
+use crate::handlers::queries::{query_rows, table_columns, validate_table_name, visible_tables};
+use crate::registry::CallContext;
+use crate::state::AppState;
+
+const SCHEMA_PREFIX: &str = "postgres://schema/";
+const TABLE_PREFIX: &str = "postgres://table/";
+const SAMPLE_SUFFIX: &str = "/sample";
+const SAMPLE_ROWS: usize = 20;
+
+/// Ressource MCP désignée par une URI `postgres://...`.
+enum ResourceUri<'a> {
+    Schema(&'a str),
+    Sample(&'a str),
+}
+
+impl<'a> ResourceUri<'a> {
+    fn parse(uri: &'a str) -> Option<Self> {
+        if let Some(table) = uri.strip_prefix(SCHEMA_PREFIX) {
+            return Some(Self::Schema(table));
+        }
+        uri.strip_prefix(TABLE_PREFIX)?
+            .strip_suffix(SAMPLE_SUFFIX)
+            .map(Self::Sample)
+    }
+}
+
+/// `resources/list` — deux ressources par table ou vue de `list_tables`, nommée
+/// comme elle (`quotes`, `market.prices`).
+#[instrument(skip(state, ctx))]
+pub async fn list_resources(state: &AppState, ctx: &CallContext) -> Result<Value, BridgeError> {
+    let tables = visible_tables(state, ctx).await?;
+    let resources: Vec<Value> = tables
+        .iter()
+        .filter_map(|t| t.get("table")?.as_str())
+        .flat_map(|table| {
+            [
+                json!({
+                    "uri": format!("{SCHEMA_PREFIX}{table}"),
+                    "name": format!("{table} schema"),
+                    "description": format!("Column definitions of '{table}'"),
+                    "mimeType": "application/json",
+                }),
+                json!({
+                    "uri": format!("{TABLE_PREFIX}{table}{SAMPLE_SUFFIX}"),
+                    "name": format!("{table} sample"),
+                    "description": format!("First {SAMPLE_ROWS} rows of '{table}'"),
+                    "mimeType": "application/json",
+                }),
+            ]
+        })
+        .collect();
+    Ok(json!({ "resources": resources }))
+}
+
+/// `resources/templates/list`
+pub fn list_resource_templates() -> Value {
+    json!({ "resourceTemplates": [
+        {
+            "uriTemplate": format!("{SCHEMA_PREFIX}{{table}}"),
+            "name": "Table schema",
+            "description": "Column definitions (name, type, nullable, default) of a table or view, as returned by describe_table.",
+            "mimeType": "application/json",
+        },
+        {
+            "uriTemplate": format!("{TABLE_PREFIX}{{table}}{SAMPLE_SUFFIX}"),
+            "name": "Table sample",
+            "description": format!("First {SAMPLE_ROWS} rows of a table or view, as a JSON array."),
+            "mimeType": "application/json",
+        }
+    ]})
+}
+
+/// `resources/read`
+#[instrument(skip(state, ctx))]
+pub async fn read_resource(
+    state: &AppState,
+    ctx: &CallContext,
+    uri: &str,
+) -> Result<Value, BridgeError> {
+    let resource = ResourceUri::parse(uri)
+        .ok_or_else(|| BridgeError::ResourceNotFound(uri.into()))?;
+
+    let text = match resource {
+        ResourceUri::Schema(table) => {
+            let columns = existing_table_columns(state, ctx, uri, table).await?;
+            serde_json::to_string_pretty(&columns).unwrap_or_else(|_| "[]".into())
+        }
+        ResourceUri::Sample(table) => {
+            // Seulement les colonnes visibles, plutôt que `*`.
+            let columns: Vec<String> = existing_table_columns(state, ctx, uri, table)
+                .await?
+                .iter()
+                .filter_map(|c| c.get("column")?.as_str())
+                .map(|c| format!("\"{}\"", c.replace('"', "\"\"")))
+                .collect();
+            // Validé par `existing_table_columns` : lettres, chiffres et `_` seulement.
+            let relation = qualified(table);
+            let (schema, name) = relation.split_once('.').unwrap_or(("public", table));
+            let sql = format!(
+                "SELECT {} FROM \"{schema}\".\"{name}\" LIMIT {SAMPLE_ROWS}",
+                columns.join(", ")
+            );
+            let rows = query_rows(state, ctx, &sql).await?;
+            serde_json::to_string_pretty(&rows).unwrap_or_else(|_| "[]".into())
+        }
+    };
+
+    Ok(json!({ "contents": [{ "uri": uri, "mimeType": "application/json", "text": text }] }))
+}
+
+async fn existing_table_columns(
+    state: &AppState,
+    ctx: &CallContext,
+    uri: &str,
+    table: &str,
+) -> Result<Vec<Value>, BridgeError> {
+    if validate_table_name(table).is_err() {
+        return Err(BridgeError::ResourceNotFound(uri.into()));
+    }
+    let columns = table_columns(state, ctx, table).await?;
+    if columns.is_empty() {
+        return Err(BridgeError::ResourceNotFound(uri.into()));
+    }
+    Ok(columns)
+}
@@ -0,0 +1,298 @@
+use std::{fs, path::Path, sync::Arc};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, instrument};
+use crate::error::BridgeError;
+use crate::handlers::format::OutputFormat;
+use crate::handlers::guard::validate_read_only;
+use crate::handlers::params::placeholder_count;
+use crate::handlers::queries::{query_output_schema, query_to_json};
+use crate::registry::{CallContext, Tool, ToolOutput, ToolRegistry};
+use crate::state::AppState;
+
+/// Fichier de configuration des outils SQL déclaratifs (`MCP_PG_TOOLS_FILE`).
+#[derive(Deserialize, Debug)]
+struct SqlToolsFile {
+    #[serde(default)]
+    tools: Vec<SqlTool>,
+}
+
+/// Requête SQL nommée et paramétrée, exposée comme outil MCP.
+/// `params` : arguments liés dans l'ordre à `$1..$n` ; `claims.<nom>` lie un claim
+/// du jeton OAuth de l'appelant (ex. `claims.sub`).
+#[derive(Deserialize, Debug)]
+pub struct SqlTool {
+    name: String,
+    #[serde(default)]
+    title: Option<String>,
+    description: String,
+    sql: String,
+    #[serde(default)]
+    params: Vec<String>,
+    #[serde(default = "default_input_schema")]
+    input_schema: Value,
+}
+
+fn default_input_schema() -> Value {
+    json!({ "type": "object", "properties": {}, "required": [] })
+}
+
+/// Charge les outils déclarés dans le fichier TOML et les enregistre.
+pub fn load_sql_tools(path: &Path, registry: &mut ToolRegistry) -> Result<(), BridgeError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| BridgeError::Api(format!("Cannot read {}: {e}", path.display())))?;
+    register_sql_tools(&content, &path.display().to_string(), registry)
+}
+
+/// Un outil ne peut pas prendre le nom d'un outil intégré ni d'un outil déjà
+/// déclaré : le remplacer changerait en silence ce qu'exécutent les agents et
+/// les scopes `tool:` qui le nomment.
+fn register_sql_tools(content: &str, source: &str, registry: &mut ToolRegistry) -> Result<(), BridgeError> {
+    let file: SqlToolsFile = toml::from_str(content)
+        .map_err(|e| BridgeError::Api(format!("Invalid tools file {source}: {e}")))?;
+
+    for mut tool in file.tools {
+        tool.validate()?;
+        if registry.get(&tool.name).is_some() {
+            return Err(BridgeError::Api(format!(
+                "SQL tool '{}' has the name of an existing tool, rename it",
+                tool.name
+            )));
+        }
+        info!(tool = %tool.name, "Registered SQL tool from {source}");
+        registry.register(Arc::new(tool));
+    }
+    Ok(())
+}
+
+impl SqlTool {
+    fn validate(&mut self) -> Result<(), BridgeError> {
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(BridgeError::Api(format!("Invalid SQL tool name: '{}'", self.name)));
+        }
+        self.sql = validate_read_only(&self.sql)
+            .map_err(|e| BridgeError::Api(format!("SQL tool '{}': {e}", self.name)))?;
+        if self.input_schema.get("type").and_then(Value::as_str) != Some("object") {
+            return Err(BridgeError::Api(format!(
+                "SQL tool '{}': input_schema must be an object schema",
+                self.name
+            )));
+        }
+        let placeholders = placeholder_count(&self.sql)
+            .map_err(|e| BridgeError::Api(format!("SQL tool '{}': {e}", self.name)))?;
+        if placeholders != self.params.len() {
+            let uses = match placeholders {
+                0 => "has no $n placeholder".to_string(),
+                n => format!("uses $1..${n}"),
+            };
+            return Err(BridgeError::Api(format!(
+                "SQL tool '{}': the SQL {uses} but {} params are listed",
+                self.name,
+                self.params.len()
+            )));
+        }
+        // `format` est le format de sortie, comme pour les outils intégrés.
+        if self.params.iter().any(|p| p == "format") {
+            return Err(BridgeError::Api(format!(
+                "SQL tool '{}': `format` is reserved for the output format, rename the param",
+                self.name
+            )));
+        }
+        match self.input_schema.get_mut("properties") {
+            Some(Value::Object(properties)) => {
+                properties.insert("format".into(), OutputFormat::schema());
+            }
+            _ => self.input_schema["properties"] = json!({ "format": OutputFormat::schema() }),
+        }
+        Ok(())
+    }
+
+    /// Valeurs liées : l'argument, sinon le `default` du schéma, sinon NULL.
+    /// Les claims du jeton ne viennent jamais des arguments.
+    fn bind_values(&self, ctx: &CallContext, args: &Value) -> Result<Vec<Value>, BridgeError> {
+        let required: Vec<&str> = self
+            .input_schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|r| r.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        self.params
+            .iter()
+            .map(|param| match (param.strip_prefix("claims."), args.get(param)) {
+                (Some(claim), _) => ctx.claim(claim).cloned().ok_or_else(|| {
+                    BridgeError::Api(format!("This tool requires an OAuth token with a '{claim}' claim"))
+                }),
+                (None, Some(v)) if !v.is_null() => Ok(v.clone()),
+                (None, _) if required.contains(&param.as_str()) => Err(BridgeError::Api(format!(
+                    "Missing required argument '{param}'"
+                ))),
+                (None, _) => Ok(self
+                    .input_schema
+                    .pointer(&format!("/properties/{param}/default"))
+                    .cloned()
+                    .unwrap_or(Value::Null)),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Tool for SqlTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn title(&self) -> Option<String> {
+        self.title.clone()
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        self.description.clone()
+    }
+
+    fn input_schema(&self) -> Value {
+        self.input_schema.clone()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(query_output_schema())
+    }
+
+    #[instrument(skip(self, state, args), fields(tool = %self.name))]
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let params = self.bind_values(ctx, args)?;
+        let format = OutputFormat::from_args(args, state.output_format)?;
+        query_to_json(state, ctx, &self.sql, &params, format).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Principal;
+    use crate::handlers::tools::register_builtins;
+    use crate::schema::LiveSchema;
+
+    const LATEST_QUOTES: &str = r#"
+        [[tools]]
+        name = "latest_quotes"
+        description = "Latest closing prices."
+        sql = "SELECT date, close FROM quotes WHERE isin = $1 AND date >= CURRENT_DATE - $2::int"
+        params = ["isin", "days"]
+
+        [tools.input_schema]
+        type = "object"
+        required = ["isin"]
+        properties.isin = { type = "string" }
+        properties.days = { type = "integer", default = 30 }
+    "#;
+
+    fn registry(content: &str) -> Result<ToolRegistry, BridgeError> {
+        let mut registry = ToolRegistry::default();
+        register_builtins(&mut registry, Arc::new(LiveSchema::new(None)));
+        register_sql_tools(content, "test", &mut registry)?;
+        Ok(registry)
+    }
+
+    fn error(content: &str) -> String {
+        match registry(content) {
+            Ok(_) => panic!("accepted:\n{content}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn declared(name: &str, sql: &str, params: &str) -> String {
+        format!("[[tools]]\nname = \"{name}\"\ndescription = \"d\"\nsql = \"{sql}\"\nparams = {params}\n")
+    }
+
+    fn parsed(content: &str) -> SqlTool {
+        let mut file: SqlToolsFile = toml::from_str(content).unwrap();
+        let mut tool = file.tools.remove(0);
+        tool.validate().unwrap();
+        tool
+    }
+
+    fn oauth_ctx(claims: Value) -> CallContext {
+        CallContext {
+            principal: Some(Arc::new(Principal::from_claims(claims, Vec::new()))),
+            ..CallContext::default()
+        }
+    }
+
+    #[test]
+    fn registers_declared_tools() {
+        let registry = registry(LATEST_QUOTES).unwrap();
+        let tool = registry.get("latest_quotes").unwrap();
+        assert_eq!(tool.input_schema()["properties"]["format"], OutputFormat::schema());
+    }
+
+    #[test]
+    fn checks_params_against_placeholders() {
+        let message = error(&declared("t", "SELECT $1, $2", r#"["a"]"#));
+        assert!(message.contains("uses $1..$2 but 1 params are listed"), "{message}");
+        assert!(error(&declared("t", "SELECT 1", r#"["a"]"#)).contains("has no $n placeholder but 1 params"));
+        // `$10` compte pour dix paramètres, même si `$2..$9` manquent.
+        assert!(error(&declared("t", "SELECT $10", r#"["a"]"#)).contains("uses $1..$10"));
+        assert!(registry(&declared("t", "SELECT '$3' AS s, $1", r#"["a"]"#)).is_ok());
+    }
+
+    #[test]
+    fn reserves_the_format_name() {
+        let message = error(&declared("t", "SELECT $1", r#"["format"]"#));
+        assert!(message.contains("`format` is reserved"), "{message}");
+    }
+
+    #[test]
+    fn refuses_the_name_of_an_existing_tool() {
+        let message = error(&declared("sql_read_query", "SELECT 1", "[]"));
+        assert!(message.contains("'sql_read_query' has the name of an existing tool"), "{message}");
+        let twice = format!("{}{}", declared("t", "SELECT 1", "[]"), declared("t", "SELECT 2", "[]"));
+        assert!(error(&twice).contains("'t' has the name of an existing tool"));
+    }
+
+    #[test]
+    fn refuses_invalid_tools() {
+        assert!(error(&declared("bad name", "SELECT 1", "[]")).contains("Invalid SQL tool name"));
+        assert!(error(&declared("t", "DELETE FROM quotes", "[]")).contains("SQL tool 't'"));
+        assert!(error("[[tools]]\nname = \"t\"").contains("Invalid tools file test"));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_then_null() {
+        let tool = parsed(LATEST_QUOTES);
+        let ctx = CallContext::default();
+        let values = |args: Value| tool.bind_values(&ctx, &args);
+        assert_eq!(values(json!({ "isin": "FR0000120271", "days": 7 })).unwrap(), [json!("FR0000120271"), json!(7)]);
+        assert_eq!(values(json!({ "isin": "FR0000120271" })).unwrap(), [json!("FR0000120271"), json!(30)]);
+        // Un `null` explicite compte comme absent.
+        assert_eq!(values(json!({ "isin": "FR0000120271", "days": null })).unwrap()[1], json!(30));
+        for args in [json!({}), json!({ "isin": null }), json!({ "days": 7 })] {
+            let error = values(args).unwrap_err().to_string();
+            assert!(error.contains("Missing required argument 'isin'"), "{error}");
+        }
+
+        let no_default = parsed(&declared("t", "SELECT $1", r#"["note"]"#));
+        assert_eq!(no_default.bind_values(&ctx, &json!({})).unwrap(), [Value::Null]);
+    }
+
+    #[test]
+    fn binds_claims_from_the_token_only() {
+        let tool = parsed(&declared("mine", "SELECT * FROM accounts WHERE owner = $1 AND id = $2", r#"["claims.sub", "id"]"#));
+        let ctx = oauth_ctx(json!({ "sub": "alice" }));
+        // Un argument nommé comme le claim ne le remplace pas.
+        let args = json!({ "claims.sub": "bob", "id": 1 });
+        assert_eq!(tool.bind_values(&ctx, &args).unwrap(), [json!("alice"), json!(1)]);
+
+        for ctx in [CallContext::default(), oauth_ctx(json!({ "iss": "https://issuer" }))] {
+            let error = tool.bind_values(&ctx, &args).unwrap_err().to_string();
+            assert!(error.contains("requires an OAuth token with a 'sub' claim"), "{error}");
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use crate::error::BridgeError;
+use crate::handlers::format::OutputFormat;
+use crate::registry::{CallContext, Tool, ToolOutput, ToolRegistry};
+use crate::schema::LiveSchema;
+use crate::state::AppState;
+use crate::handlers::queries::{
+    sql_read_query, continue_cursor, list_tables, describe_table, query_output_schema,
+    portfolio_performance, at_risk_positions, sector_exposure,
+};
+
+/// Enregistre les outils intégrés de `handlers/queries.rs`, et `query_history`.
+pub fn register_builtins(registry: &mut ToolRegistry, schema: Arc<LiveSchema>) {
+    registry.register(Arc::new(SqlReadQuery { schema }));
+    registry.register(Arc::new(ListTables));
+    registry.register(Arc::new(DescribeTable));
+    registry.register(Arc::new(QueryHistory));
+    registry.register(Arc::new(PortfolioPerformance));
+    registry.register(Arc::new(AtRiskPositions));
+    registry.register(Arc::new(SectorExposure));
+}
+
+fn empty_schema() -> Value {
+    json!({ "type": "object", "properties": {}, "required": [] })
+}
+
+/// Schéma d'entrée des outils d'analyse qui ne prennent que le format de sortie.
+fn format_only_schema() -> Value {
+    json!({ "type": "object", "properties": { "format": OutputFormat::schema() }, "required": [] })
+}
+
+// ─────────────────────────────────────────
+// Outils génériques
+// ─────────────────────────────────────────
+
+/// Sa description embarque le schéma généré depuis le catalogue.
+pub struct SqlReadQuery {
+    schema: Arc<LiveSchema>,
+}
+
+#[async_trait]
+impl Tool for SqlReadQuery {
+    fn name(&self) -> &str {
+        "sql_read_query"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("Read-only SQL query".into())
+    }
+
+    /// Ne décrit que les tables que le jeton de l'appelant peut interroger.
+    fn description(&self, ctx: &CallContext) -> String {
+        let mut description = String::from(concat!(
+            "Query the pAItrimony financial database. ",
+            "Use this tool to answer any question about the user's investment portfolio: ",
+            "account balances, holdings, historical stock prices (OHLCV), ",
+            "technical indicators (RSI, SMA, Bollinger Bands), or news sentiment scores. ",
+            "Input must be a valid SELECT query. ",
+            "Never paste user-provided values (tickers, dates, names) into the SQL text: ",
+            "write $1, $2, ... placeholders and pass the values in `params`. ",
+            "Results come in pages of 500 rows, read from a snapshot taken by the first page; ",
+            "ORDER BY what the pages should follow, and read every page you need.\n"
+        ));
+        if let Some(preamble) = self.schema.preamble() {
+            description.push_str(preamble);
+            description.push('\n');
+        }
+        let schema = self.schema.text(|table| ctx.allows_table(table));
+        if schema.is_empty() {
+            description.push_str("Use list_tables and describe_table to discover the schema.");
+        } else {
+            description.push_str(
+                "The database schema is (column type, [enum values], -> foreign key, /* comment */):\n",
+            );
+            description.push_str(&schema);
+        }
+        description
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "sql": {
+                    "type": "string",
+                    "description": "A valid SELECT or WITH...SELECT SQL query against the schema above, with $1..$n placeholders for values."
+                },
+                "params": {
+                    "type": "array",
+                    "description": concat!(
+                        "Values bound to $1..$n, in order. Types are inferred: strings (text), ",
+                        "'YYYY-MM-DD' (date), ISO 8601 date-times (timestamp, or timestamptz with an offset), ",
+                        "integers (bigint), decimals (double precision), booleans, null, ",
+                        "and arrays of one scalar type (use = ANY($n)). Add an explicit cast ($1::numeric) if needed."
+                    ),
+                    "items": {}
+                },
+                "cursor": {
+                    "type": "string",
+                    "description": "next_cursor returned with a truncated result. When set, sql, params and format are ignored and the next page is read from a snapshot of the result taken by the first page: rows changed since then do not shift the pages."
+                },
+                "format": OutputFormat::schema()
+            },
+            "required": []
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(query_output_schema())
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        if let Some(cursor) = args.get("cursor").and_then(Value::as_str) {
+            return continue_cursor(state, ctx, cursor).await;
+        }
+        let sql = args.get("sql").and_then(Value::as_str).unwrap_or("");
+        let params = match args.get("params") {
+            None | Some(Value::Null) => &[][..],
+            Some(Value::Array(params)) => params.as_slice(),
+            Some(_) => return Err(BridgeError::InvalidParams("'params' must be an array".into())),
+        };
+        let format = OutputFormat::from_args(args, state.output_format)?;
+        sql_read_query(state, ctx, sql, params, format).await
+    }
+}
+
+pub struct ListTables;
+
+#[async_trait]
+impl Tool for ListTables {
+    fn name(&self) -> &str {
+        "list_tables"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("List tables".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "List all tables and views available in the financial database. ",
+            "Call this first if you are unsure which tables exist."
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        empty_schema()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "tables": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "table": {
+                                "type": "string",
+                                "description": "schema.table, or the bare name in public"
+                            },
+                            "type": { "type": "string", "description": "BASE TABLE or VIEW" }
+                        },
+                        "required": ["table", "type"]
+                    }
+                }
+            },
+            "required": ["tables"]
+        }))
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        _args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        list_tables(state, ctx).await
+    }
+}
+
+pub struct DescribeTable;
+
+#[async_trait]
+impl Tool for DescribeTable {
+    fn name(&self) -> &str {
+        "describe_table"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("Describe table".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "Get the column definitions of a specific table or view in the financial database. ",
+            "Use this before writing a sql_read_query if you need to know ",
+            "the exact column names and types. ",
+            "Key tables: quotes, holdings, signals, news, view_portfolio_summary."
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "table": {
+                    "type": "string",
+                    "description": concat!(
+                        "Table or view name, e.g. 'quotes', 'holdings', ",
+                        "'signals', 'news', 'view_portfolio_summary'. ",
+                        "Outside the public schema, write schema.table as list_tables does."
+                    )
+                }
+            },
+            "required": ["table"]
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "columns": {
+                    "type": "array",
+                    "description": "Empty when the table does not exist.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "column": { "type": "string" },
+                            "type": { "type": "string" },
+                            "nullable": { "type": "boolean" },
+                            "default": { "type": ["string", "null"] }
+                        },
+                        "required": ["column", "type", "nullable", "default"]
+                    }
+                }
+            },
+            "required": ["columns"]
+        }))
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let table = args.get("table").and_then(Value::as_str).unwrap_or("");
+        describe_table(state, ctx, table).await
+    }
+}
+
+pub struct QueryHistory;
+
+#[async_trait]
+impl Tool for QueryHistory {
+    fn name(&self) -> &str {
+        "query_history"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("Query history".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "List your own recent tool calls in this session, newest first: ",
+            "tool, arguments, SQL run, duration, row count, whether the result was truncated, and the error if any. ",
+            "Use this to reuse or fix a query you ran earlier instead of rewriting it. ",
+            "Empty without a session or a bearer token."
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "limit": {
+                    "type": "integer",
+                    "description": "Number of calls to return (default: 20)."
+                },
+                "errors_only": {
+                    "type": "boolean",
+                    "description": "Only return the calls that failed (default: false)."
+                }
+            },
+            "required": []
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "queries": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "timestamp": { "type": "string" },
+                            "tool": { "type": "string" },
+                            "arguments": { "type": "object" },
+                            "sql": { "type": ["string", "null"] },
+                            "duration_ms": { "type": "integer" },
+                            "row_count": { "type": ["integer", "null"] },
+                            "truncated": { "type": "boolean" },
+                            "error": { "type": ["string", "null"] }
+                        },
+                        "required": ["timestamp", "tool", "arguments", "sql", "duration_ms", "row_count", "truncated", "error"]
+                    }
+                }
+            },
+            "required": ["queries"]
+        }))
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let limit = args.get("limit").and_then(Value::as_u64).unwrap_or(20) as usize;
+        let errors_only = args.get("errors_only").and_then(Value::as_bool).unwrap_or(false);
+        let queries: Vec<Value> = state
+            .audit
+            .history(ctx, limit, errors_only)
+            .into_iter()
+            .map(|record| {
+                json!({
+                    "timestamp": record.timestamp,
+                    "tool": record.tool,
+                    "arguments": record.arguments,
+                    "sql": record.sql,
+                    "duration_ms": record.duration_ms,
+                    "row_count": record.row_count,
+                    "truncated": record.truncated,
+                    "error": record.error,
+                })
+            })
+            .collect();
+        let text = match queries.is_empty() {
+            true => "No earlier tool call in this session.".into(),
+            false => serde_json::to_string_pretty(&queries).unwrap_or_else(|_| "[]".into()),
+        };
+        Ok(ToolOutput { text, structured: Some(json!({ "queries": queries })) })
+    }
+}
+
+// ─────────────────────────────────────────
+// Outils métier
+// ─────────────────────────────────────────
+
+pub struct PortfolioPerformance;
+
+#[async_trait]
+impl Tool for PortfolioPerformance {
+    fn name(&self) -> &str {
+        "portfolio_performance"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("Portfolio performance".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "Returns the current state of the user's investment portfolio: ",
+            "all positions across all accounts (PEA, CTO, Crypto, SCPI) ",
+            "with current market value, cost basis, unrealized profit/loss in currency and percentage. ",
+            "Uses view_portfolio_summary internally (isin-based joins). ",
+            "Use this to answer questions like: ",
+            "'How is my portfolio doing?', ",
+            "'What are my best/worst performing positions?', ",
+            "'What is my total portfolio value?'"
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        format_only_schema()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(query_output_schema())
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let format = OutputFormat::from_args(args, state.output_format)?;
+        portfolio_performance(state, ctx, format).await
+    }
+}
+
+pub struct AtRiskPositions;
+
+#[async_trait]
+impl Tool for AtRiskPositions {
+    fn name(&self) -> &str {
+        "at_risk_positions"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("At-risk positions".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "Returns positions that are currently at risk, defined as: ",
+            "a loss exceeding the drawdown threshold (default: -10%) ",
+            "OR a negative average news sentiment over the last 7 days (below -0.5). ",
+            "Also returns RSI and moving averages (SMA50, SMA200) for each flagged position. ",
+            "Note: signals are joined via ticker, news via ticker. ",
+            "Use this to answer questions like: ",
+            "'What positions should I be worried about?', ",
+            "'Are there any alerts in my portfolio?', ",
+            "'Which stocks have bad news sentiment?'"
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "drawdown_threshold": {
+                    "type": "number",
+                    "description": "Loss percentage threshold (default: 10.0 — flags positions down more than 10%)"
+                },
+                "format": OutputFormat::schema()
+            },
+            "required": []
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(query_output_schema())
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let threshold = args
+            .get("drawdown_threshold")
+            .and_then(Value::as_f64)
+            .unwrap_or(10.0);
+        let format = OutputFormat::from_args(args, state.output_format)?;
+        at_risk_positions(state, ctx, threshold, format).await
+    }
+}
+
+pub struct SectorExposure;
+
+#[async_trait]
+impl Tool for SectorExposure {
+    fn name(&self) -> &str {
+        "sector_exposure"
+    }
+
+    fn title(&self) -> Option<String> {
+        Some("Sector exposure".into())
+    }
+
+    fn description(&self, _ctx: &CallContext) -> String {
+        concat!(
+            "Returns the user's portfolio allocation broken down by market sector ",
+            "(Technology, Finance, Energy, etc.): number of positions per sector, ",
+            "total invested value, and percentage of the total portfolio. ",
+            "Joins via isin: holdings → assets. ",
+            "Use this to answer questions like: ",
+            "'Am I too exposed to Tech?', ",
+            "'What is my sector diversification?', ",
+            "'Should I rebalance my portfolio?'"
+        )
+        .into()
+    }
+
+    fn input_schema(&self) -> Value {
+        format_only_schema()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(query_output_schema())
+    }
+
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError> {
+        let format = OutputFormat::from_args(args, state.output_format)?;
+        sector_exposure(state, ctx, format).await
+    }
+}
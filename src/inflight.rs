@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use crate::error::BridgeError;
+use crate::registry::CallContext;
+
+/// Requête JSON-RPC en cours : le backend PostgreSQL qui exécute sa requête SQL,
+/// et si le client l'a annulée.
+#[derive(Default)]
+pub struct RequestHandle {
+    state: Mutex<RequestState>,
+}
+
+#[derive(Default)]
+struct RequestState {
+    /// Transaction de la requête, pendant qu'elle s'exécute.
+    backend: Option<Backend>,
+    cancelled: bool,
+}
+
+/// Transaction d'un backend : son PID et l'heure de début de la transaction
+/// (`now()`, le `xact_start` de `pg_stat_activity`), qui distingue deux
+/// transactions successives d'une même connexion du pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backend {
+    pub pid: i32,
+    pub xact_start: DateTime<Utc>,
+}
+
+impl RequestHandle {
+    /// Rattache la transaction qui vient de s'ouvrir jusqu'à l'abandon de la
+    /// garde renvoyée. Échoue si la requête a été annulée avant de démarrer.
+    pub fn attach(self: &Arc<Self>, backend: Backend) -> Result<AttachedBackend, BridgeError> {
+        let mut state = self.state.lock().unwrap();
+        if state.cancelled {
+            return Err(BridgeError::Api("Request cancelled".into()));
+        }
+        state.backend = Some(backend);
+        Ok(AttachedBackend(self.clone()))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    /// Marque la requête annulée ; renvoie la transaction à annuler, s'il y en a une.
+    fn cancel(&self) -> Option<Backend> {
+        let mut state = self.state.lock().unwrap();
+        state.cancelled = true;
+        state.backend.take()
+    }
+}
+
+/// Détache la transaction de sa requête à l'abandon, sur tous les chemins :
+/// une annulation tardive ne vise alors plus cette transaction.
+pub struct AttachedBackend(Arc<RequestHandle>);
+
+impl Drop for AttachedBackend {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().backend = None;
+    }
+}
+
+impl fmt::Debug for RequestHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("RequestHandle")
+            .field("backend", &state.backend)
+            .field("cancelled", &state.cancelled)
+            .finish()
+    }
+}
+
+/// Session, nom du jeton, requête HTTP anonyme et id JSON-RPC : sans session,
+/// un jeton n'annule que ses propres requêtes, et un appelant anonyme sans état
+/// seulement celles de la même requête HTTP.
+type RequestKey = (Option<String>, Option<String>, Option<String>, String);
+
+fn request_key(ctx: &CallContext, id: &Value) -> RequestKey {
+    (
+        ctx.session_id.clone(),
+        ctx.principal_name().map(String::from),
+        ctx.anonymous_request.clone(),
+        id.to_string(),
+    )
+}
+
+/// Requêtes en cours, indexées par `RequestKey`.
+#[derive(Clone)]
+pub struct InFlightRequests {
+    pool: PgPool,
+    requests: Arc<Mutex<HashMap<RequestKey, Arc<RequestHandle>>>>,
+}
+
+impl InFlightRequests {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool, requests: Arc::default() }
+    }
+
+    /// Suit une requête jusqu'à l'abandon de la garde renvoyée. Une garde
+    /// abandonnée avant `finish` (client parti en cours de route) annule la requête.
+    pub fn register(&self, ctx: &CallContext, id: &Value) -> InFlightGuard {
+        let key = request_key(ctx, id);
+        let handle = Arc::new(RequestHandle::default());
+        self.requests.lock().unwrap().insert(key.clone(), handle.clone());
+        InFlightGuard { requests: self.clone(), key, handle, finished: false }
+    }
+
+    /// `notifications/cancelled` : annule la requête `id` du même appelant.
+    pub fn cancel(&self, ctx: &CallContext, id: &Value) {
+        let key = request_key(ctx, id);
+        let handle = self.requests.lock().unwrap().get(&key).cloned();
+        match handle {
+            Some(handle) => {
+                info!(request = %key.3, "Cancelling request");
+                self.cancel_handle(&handle);
+            }
+            None => info!(request = %key.3, "Cancellation for an unknown or finished request"),
+        }
+    }
+
+    /// Annule toutes les requêtes d'une session dont le client s'est déconnecté.
+    pub fn cancel_session(&self, session_id: &str) {
+        let handles: Vec<Arc<RequestHandle>> = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((session, _, _, _), _)| session.as_deref() == Some(session_id))
+            .map(|(_, handle)| handle.clone())
+            .collect();
+        for handle in handles {
+            self.cancel_handle(&handle);
+        }
+    }
+
+    fn cancel_handle(&self, handle: &RequestHandle) {
+        let Some(Backend { pid, xact_start }) = handle.cancel() else { return };
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            // Le backend peut avoir fini la transaction et servir une autre requête
+            // depuis : on n'annule que s'il est toujours dans la même.
+            let result = sqlx::query_scalar::<_, bool>(
+                "SELECT pg_cancel_backend(pid) FROM pg_stat_activity \
+                 WHERE pid = $1 AND xact_start = $2",
+            )
+            .bind(pid)
+            .bind(xact_start)
+            .fetch_optional(&pool)
+            .await;
+            match result {
+                Ok(Some(true)) => info!(pid, "Cancelled PostgreSQL query"),
+                Ok(Some(false)) => warn!(pid, "pg_cancel_backend had no effect"),
+                Ok(None) => info!(pid, "Transaction already ended, nothing to cancel"),
+                Err(e) => warn!(pid, error = %e, "pg_cancel_backend failed"),
+            }
+        });
+    }
+}
+
+pub struct InFlightGuard {
+    requests: InFlightRequests,
+    key: RequestKey,
+    handle: Arc<RequestHandle>,
+    finished: bool,
+}
+
+impl InFlightGuard {
+    pub fn handle(&self) -> Arc<RequestHandle> {
+        self.handle.clone()
+    }
+
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut requests = self.requests.requests.lock().unwrap();
+        // Un client qui réutilise un id remplace l'entrée : on laisse la plus récente.
+        if requests.get(&self.key).is_some_and(|h| Arc::ptr_eq(h, &self.handle)) {
+            requests.remove(&self.key);
+        }
+        drop(requests);
+        if !self.finished {
+            self.requests.cancel_handle(&self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> Backend {
+        Backend { pid: 42, xact_start: Utc::now() }
+    }
+
+    #[test]
+    fn dropping_the_guard_detaches_the_transaction() {
+        let handle = Arc::new(RequestHandle::default());
+        let attached = handle.attach(backend()).unwrap();
+        drop(attached);
+        // La transaction est terminée : l'annulation ne vise plus rien.
+        assert_eq!(handle.cancel(), None);
+        assert!(handle.is_cancelled());
+    }
+
+    #[test]
+    fn cancel_targets_the_attached_transaction_once() {
+        let handle = Arc::new(RequestHandle::default());
+        let backend = backend();
+        let _attached = handle.attach(backend).unwrap();
+        assert_eq!(handle.cancel(), Some(backend));
+        assert_eq!(handle.cancel(), None);
+    }
+
+    #[test]
+    fn a_cancelled_request_cannot_attach() {
+        let handle = Arc::new(RequestHandle::default());
+        handle.cancel();
+        assert!(handle.attach(backend()).is_err());
+    }
+}
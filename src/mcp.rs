@@ -1,4 +1,60 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Codes d'erreur JSON-RPC 2.0 standard.
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+// Code spécifique MCP.
+pub const RESOURCE_NOT_FOUND: i32 = -32002;
+
+/// Révisions du protocole MCP supportées, la plus récente d'abord.
+pub const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+/// Supposée pour les requêtes Streamable HTTP sans session ni en-tête
+/// `MCP-Protocol-Version`, comme le prescrit la révision 2025-06-18.
+pub const DEFAULT_HTTP_PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Version répondue à `initialize` : celle demandée par le client si elle est
+/// supportée, sinon la plus récente (le client décide alors de continuer ou non).
+pub fn negotiate_protocol_version(requested: Option<&str>) -> &'static str {
+    PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0])
+}
+
+pub fn is_supported_protocol_version(version: &str) -> bool {
+    PROTOCOL_VERSIONS.contains(&version)
+}
+
+/// Fonctionnalités activées selon la révision négociée.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolFeatures {
+    /// `annotations` (indices de lecture seule) sur les outils — 2025-03-26.
+    pub tool_annotations: bool,
+    /// `outputSchema` / `structuredContent` — 2025-06-18.
+    pub structured_output: bool,
+    /// `title` sur les outils et `serverInfo` — 2025-06-18.
+    pub titles: bool,
+    /// `message` dans `notifications/progress` — 2025-03-26.
+    pub progress_messages: bool,
+}
+
+impl ProtocolFeatures {
+    /// Fonctionnalités de `version` ; inconnue, c'est la plus ancienne révision supportée.
+    pub fn of(version: Option<&str>) -> Self {
+        // Les révisions sont des dates : elles se comparent comme des chaînes.
+        let version = version.unwrap_or(PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1]);
+        Self {
+            tool_annotations: version >= "2025-03-26",
+            structured_output: version >= "2025-06-18",
+            titles: version >= "2025-06-18",
+            progress_messages: version >= "2025-03-26",
+        }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct McpRequest {
@@ -8,9 +64,164 @@ pub struct McpRequest {
     pub params: Option<serde_json::Value>,
 }
 
+impl McpRequest {
+    /// Décode un message JSON-RPC brut. En cas d'échec, renvoie la réponse
+    /// d'erreur à envoyer : `-32700` pour un JSON invalide, `-32600` pour une
+    /// enveloppe mal formée.
+    pub fn parse(body: &[u8]) -> Result<Self, Box<McpResponse>> {
+        let value: Value = serde_json::from_slice(body).map_err(|e| {
+            Box::new(McpResponse::error(Value::Null, JsonRpcError::new(PARSE_ERROR, "Parse error")
+                .with_data(json!({ "detail": e.to_string() }))))
+        })?;
+
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        let request: McpRequest = serde_json::from_value(value).map_err(|e| {
+            Box::new(McpResponse::error(id.clone(), JsonRpcError::new(INVALID_REQUEST, "Invalid Request")
+                .with_data(json!({ "detail": e.to_string() }))))
+        })?;
+        if request.jsonrpc != "2.0" {
+            return Err(Box::new(McpResponse::error(id, JsonRpcError::new(INVALID_REQUEST, "Invalid Request")
+                .with_data(json!({ "detail": "jsonrpc must be \"2.0\"" })))));
+        }
+        Ok(request)
+    }
+
+    /// Pour `initialize`, la version du protocole qu'utilisera la session.
+    pub fn negotiated_protocol_version(&self) -> Option<&'static str> {
+        (self.method == "initialize").then(|| {
+            negotiate_protocol_version(
+                self.params.as_ref().and_then(|p| p.get("protocolVersion")?.as_str()),
+            )
+        })
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct McpResponse {
     pub jsonrpc: String,
     pub id: serde_json::Value,
-    pub result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl McpResponse {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0".into(), id, result: Some(result), error: None }
+    }
+
+    pub fn error(id: Value, error: JsonRpcError) -> Self {
+        Self { jsonrpc: "2.0".into(), id, result: None, error: Some(error) }
+    }
+}
+
+/// Notification JSON-RPC émise par le serveur (pas d'`id`, pas de réponse).
+#[derive(Serialize, Debug)]
+pub struct McpNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+impl McpNotification {
+    pub fn new(method: impl Into<String>, params: Option<Value>) -> Self {
+        Self { jsonrpc: "2.0".into(), method: method.into(), params }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct JsonRpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: Option<Value>) -> McpRequest {
+        McpRequest { jsonrpc: "2.0".into(), id: Some(json!(1)), method: method.into(), params }
+    }
+
+    #[test]
+    fn keeps_a_supported_version() {
+        for version in PROTOCOL_VERSIONS {
+            assert_eq!(negotiate_protocol_version(Some(version)), *version);
+        }
+    }
+
+    #[test]
+    fn answers_the_latest_otherwise() {
+        assert_eq!(negotiate_protocol_version(Some("2023-01-01")), PROTOCOL_VERSIONS[0]);
+        assert_eq!(negotiate_protocol_version(Some("2099-01-01")), PROTOCOL_VERSIONS[0]);
+        assert_eq!(negotiate_protocol_version(None), PROTOCOL_VERSIONS[0]);
+    }
+
+    #[test]
+    fn negotiates_on_initialize_only() {
+        let init = request("initialize", Some(json!({ "protocolVersion": "2024-11-05" })));
+        assert_eq!(init.negotiated_protocol_version(), Some("2024-11-05"));
+
+        let no_version = request("initialize", Some(json!({})));
+        assert_eq!(no_version.negotiated_protocol_version(), Some(PROTOCOL_VERSIONS[0]));
+
+        let not_a_string = request("initialize", Some(json!({ "protocolVersion": 20250618 })));
+        assert_eq!(not_a_string.negotiated_protocol_version(), Some(PROTOCOL_VERSIONS[0]));
+
+        let call = request("tools/list", Some(json!({ "protocolVersion": "2024-11-05" })));
+        assert_eq!(call.negotiated_protocol_version(), None);
+    }
+
+    #[test]
+    fn recognises_supported_versions() {
+        assert!(is_supported_protocol_version("2025-06-18"));
+        assert!(is_supported_protocol_version(DEFAULT_HTTP_PROTOCOL_VERSION));
+        assert!(!is_supported_protocol_version("2025-06-19"));
+        assert!(!is_supported_protocol_version(""));
+    }
+
+    #[test]
+    fn gates_features_on_the_revision() {
+        let latest = ProtocolFeatures::of(Some("2025-06-18"));
+        assert!(latest.tool_annotations && latest.structured_output && latest.titles && latest.progress_messages);
+
+        let middle = ProtocolFeatures::of(Some("2025-03-26"));
+        assert!(middle.tool_annotations && middle.progress_messages);
+        assert!(!middle.structured_output && !middle.titles);
+
+        for oldest in [ProtocolFeatures::of(Some("2024-11-05")), ProtocolFeatures::of(None)] {
+            assert!(!oldest.tool_annotations && !oldest.structured_output);
+            assert!(!oldest.titles && !oldest.progress_messages);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let error = McpRequest::parse(b"{not json").unwrap_err();
+        assert_eq!(error.error.as_ref().unwrap().code, PARSE_ERROR);
+        assert_eq!(error.id, Value::Null);
+
+        let error = McpRequest::parse(br#"{"jsonrpc":"1.0","id":7,"method":"ping"}"#).unwrap_err();
+        assert_eq!(error.error.as_ref().unwrap().code, INVALID_REQUEST);
+        assert_eq!(error.id, json!(7));
+
+        let error = McpRequest::parse(br#"{"jsonrpc":"2.0","id":8}"#).unwrap_err();
+        assert_eq!(error.error.as_ref().unwrap().code, INVALID_REQUEST);
+        assert_eq!(error.id, json!(8));
+    }
 }
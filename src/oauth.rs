@@ -0,0 +1,336 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use crate::auth::Principal;
+use crate::error::BridgeError;
+use crate::state::AppState;
+
+/// Algorithmes asymétriques seulement : le pont ne partage aucun secret avec l'émetteur.
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384, Algorithm::EdDSA,
+];
+/// Un jeton signé d'un `kid` inconnu déclenche au plus un rechargement JWKS par intervalle.
+const JWKS_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+const METADATA_PATH: &str = "/.well-known/oauth-protected-resource";
+
+/// Pourquoi une requête n'a pas été authentifiée ; devient le challenge `WWW-Authenticate`.
+#[derive(Debug)]
+pub enum AuthError {
+    Missing,
+    InvalidToken(String),
+    InsufficientScope(String),
+}
+
+enum JwksSource {
+    File(PathBuf),
+    Url(String),
+    /// `jwks_uri` des métadonnées de l'émetteur, cherché au premier usage.
+    Discover,
+}
+
+/// Serveur protégé au sens OAuth 2.1 : valide les jetons JWT d'un émetteur.
+///
+/// Configuré par `MCP_PG_OAUTH_ISSUER` et `MCP_PG_OAUTH_RESOURCE` (l'URL canonique
+/// de ce serveur, aussi l'audience attendue par défaut).
+pub struct OAuth {
+    issuer: String,
+    resource: String,
+    audience: String,
+    required_scopes: Vec<String>,
+    source: JwksSource,
+    keys: RwLock<JwkSet>,
+    last_reload: Mutex<Option<Instant>>,
+    http: reqwest::Client,
+}
+
+impl OAuth {
+    /// `Ok(None)` si `MCP_PG_OAUTH_ISSUER` n'est pas défini.
+    pub async fn from_env(prefix: &str) -> Result<Option<Self>, BridgeError> {
+        let var = |name: &str| env::var(format!("{prefix}_{name}")).ok().filter(|v| !v.is_empty());
+        let Some(issuer) = var("OAUTH_ISSUER") else {
+            return Ok(None);
+        };
+        let resource = var("OAUTH_RESOURCE").ok_or_else(|| {
+            BridgeError::Api(format!("{prefix}_OAUTH_RESOURCE is required with {prefix}_OAUTH_ISSUER"))
+        })?;
+        Url::parse(&resource)
+            .map_err(|e| BridgeError::Api(format!("Invalid {prefix}_OAUTH_RESOURCE: {e}")))?;
+        let source = match var("OAUTH_JWKS") {
+            Some(jwks) if jwks.starts_with("http://") || jwks.starts_with("https://") => {
+                JwksSource::Url(jwks)
+            }
+            Some(path) => JwksSource::File(path.into()),
+            None => JwksSource::Discover,
+        };
+        let oauth = Self {
+            audience: var("OAUTH_AUDIENCE").unwrap_or_else(|| resource.clone()),
+            required_scopes: var("OAUTH_REQUIRED_SCOPES")
+                .map(|s| s.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+            issuer,
+            resource,
+            source,
+            keys: RwLock::new(JwkSet { keys: Vec::new() }),
+            last_reload: Mutex::new(None),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .map_err(|e| BridgeError::Api(format!("Cannot build HTTP client: {e}")))?,
+        };
+
+        // Fichier local invalide : erreur de configuration ; un émetteur injoignable peut revenir.
+        match (&oauth.source, oauth.reload_keys().await) {
+            (_, Ok(count)) => info!(issuer = %oauth.issuer, keys = count, "OAuth token validation enabled"),
+            (JwksSource::File(_), Err(e)) => return Err(e),
+            (_, Err(e)) => warn!(error = %e, "Could not load the issuer JWKS, retrying on first token"),
+        }
+        Ok(Some(oauth))
+    }
+
+    async fn fetch_json(&self, url: &str) -> Result<Value, BridgeError> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| BridgeError::Api(format!("GET {url}: {e}")))?;
+        response
+            .json()
+            .await
+            .map_err(|e| BridgeError::Api(format!("GET {url}: {e}")))
+    }
+
+    /// `jwks_uri` d'après les métadonnées RFC 8414, ou la configuration OpenID.
+    async fn discover_jwks_uri(&self) -> Result<String, BridgeError> {
+        let issuer = self.issuer.trim_end_matches('/');
+        let mut candidates = Vec::new();
+        if let Ok(url) = Url::parse(issuer) {
+            let origin = url.origin().ascii_serialization();
+            let path = url.path().trim_end_matches('/');
+            candidates.push(format!("{origin}/.well-known/oauth-authorization-server{path}"));
+        }
+        candidates.push(format!("{issuer}/.well-known/openid-configuration"));
+
+        let mut last_error = None;
+        for url in candidates {
+            match self.fetch_json(&url).await {
+                Ok(metadata) => match metadata.get("jwks_uri").and_then(Value::as_str) {
+                    Some(jwks_uri) => return Ok(jwks_uri.into()),
+                    None => last_error = Some(BridgeError::Api(format!("{url} has no jwks_uri"))),
+                },
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| BridgeError::Api("No issuer metadata".into())))
+    }
+
+    /// Remplace les clés en cache ; renvoie combien ont été chargées.
+    async fn reload_keys(&self) -> Result<usize, BridgeError> {
+        *self.last_reload.lock().unwrap() = Some(Instant::now());
+        let jwks: JwkSet = match &self.source {
+            JwksSource::File(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| BridgeError::Api(format!("Cannot read {}: {e}", path.display())))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| BridgeError::Api(format!("Invalid JWKS {}: {e}", path.display())))?
+            }
+            JwksSource::Url(url) => serde_json::from_value(self.fetch_json(url).await?)
+                .map_err(|e| BridgeError::Api(format!("Invalid JWKS at {url}: {e}")))?,
+            JwksSource::Discover => {
+                let url = self.discover_jwks_uri().await?;
+                serde_json::from_value(self.fetch_json(&url).await?)
+                    .map_err(|e| BridgeError::Api(format!("Invalid JWKS at {url}: {e}")))?
+            }
+        };
+        let count = jwks.keys.len();
+        *self.keys.write().unwrap() = jwks;
+        Ok(count)
+    }
+
+    fn find_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let keys = self.keys.read().unwrap();
+        match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            // Sans `kid`, seul un jeu d'une seule clé est utilisable sans ambiguïté.
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        }
+    }
+
+    /// Cherche la clé de signature, en rechargeant le JWKS une fois si elle est
+    /// inconnue (rotation), mais pas plus d'une fois par `JWKS_RELOAD_INTERVAL`.
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, AuthError> {
+        if let Some(key) = self.find_key(kid) {
+            return Ok(key);
+        }
+        let recently = self
+            .last_reload
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < JWKS_RELOAD_INTERVAL);
+        if !recently && let Err(e) = self.reload_keys().await {
+            warn!(error = %e, "JWKS reload failed");
+        }
+        self.find_key(kid)
+            .ok_or_else(|| AuthError::InvalidToken("Unknown signing key".into()))
+    }
+
+    /// Vérifie signature, émetteur, audience, `nbf` et expiration, puis les scopes requis.
+    pub async fn validate(&self, token: &str) -> Result<Principal, AuthError> {
+        let header = decode_header(token).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(AuthError::InvalidToken(format!("Algorithm {:?} not accepted", header.alg)));
+        }
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        if !key_allows(&jwk, header.alg) {
+            return Err(AuthError::InvalidToken(format!(
+                "Algorithm {:?} does not match the signing key",
+                header.alg
+            )));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.validate_nbf = true;
+        let claims = decode::<Value>(token, &key, &validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+        if claims.get("sub").and_then(Value::as_str).is_none_or(str::is_empty) {
+            return Err(AuthError::InvalidToken("Empty sub".into()));
+        }
+
+        let scopes = token_scopes(&claims);
+        let missing: Vec<&str> = self
+            .required_scopes
+            .iter()
+            .filter(|s| !scopes.contains(s))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(AuthError::InsufficientScope(self.required_scopes.join(" ")));
+        }
+        Ok(Principal::from_claims(claims, scopes))
+    }
+
+    /// URL du document de métadonnées pour `WWW-Authenticate` (RFC 9728 : le
+    /// chemin well-known inséré avant le chemin de la ressource).
+    pub fn metadata_url(&self) -> String {
+        match Url::parse(&self.resource) {
+            Ok(url) => format!(
+                "{}{METADATA_PATH}{}",
+                url.origin().ascii_serialization(),
+                url.path().trim_end_matches('/')
+            ),
+            Err(_) => METADATA_PATH.into(),
+        }
+    }
+
+    fn metadata(&self) -> Value {
+        let mut scopes: Vec<&str> = self.required_scopes.iter().map(String::as_str).collect();
+        scopes.extend(["tool:<name>", "table:<name>"]);
+        json!({
+            "resource": self.resource,
+            "authorization_servers": [self.issuer],
+            "bearer_methods_supported": ["header"],
+            "scopes_supported": scopes,
+            "resource_name": "PostgreSQL MCP bridge",
+        })
+    }
+}
+
+/// Une clé qui déclare son `alg` ne vérifie que les jetons de cet algorithme
+/// (RFC 7517 §4.4) : un jeton ne peut pas lui en imposer un autre.
+fn key_allows(jwk: &Jwk, alg: Algorithm) -> bool {
+    jwk.common
+        .key_algorithm
+        .is_none_or(|declared| format!("{declared:?}") == format!("{alg:?}"))
+}
+
+/// `scope` (RFC 9068, séparés par des espaces) ou `scp` (chaîne ou tableau).
+fn token_scopes(claims: &Value) -> Vec<String> {
+    let mut scopes = Vec::new();
+    for claim in ["scope", "scp"] {
+        match claims.get(claim) {
+            Some(Value::String(s)) => scopes.extend(s.split_whitespace().map(String::from)),
+            Some(Value::Array(items)) => {
+                scopes.extend(items.iter().filter_map(Value::as_str).map(String::from))
+            }
+            _ => {}
+        }
+    }
+    scopes
+}
+
+/// Scopes `tool:<nom>` / `table:<nom>`, ou `None` si le jeton n'en a aucun de ce type.
+pub fn scoped(scopes: &[String], prefix: &str) -> Option<HashSet<String>> {
+    let set: HashSet<String> = scopes
+        .iter()
+        .filter_map(|s| s.strip_prefix(prefix))
+        .map(String::from)
+        .collect();
+    (!set.is_empty()).then_some(set)
+}
+
+/// `GET /.well-known/oauth-protected-resource[/...]` — métadonnées RFC 9728.
+pub async fn protected_resource_metadata(State(state): State<Arc<AppState>>) -> Response {
+    match state.auth.as_ref().and_then(|auth| auth.oauth()) {
+        Some(oauth) => Json(oauth.metadata()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwk(alg: Option<&str>) -> Jwk {
+        let mut key = json!({ "kty": "EC", "crv": "P-256", "x": "AA", "y": "AA" });
+        if let Some(alg) = alg {
+            key["alg"] = alg.into();
+        }
+        serde_json::from_value(key).unwrap()
+    }
+
+    #[test]
+    fn keys_only_verify_their_algorithm() {
+        assert!(key_allows(&jwk(Some("ES256")), Algorithm::ES256));
+        assert!(!key_allows(&jwk(Some("ES256")), Algorithm::ES384));
+        assert!(!key_allows(&jwk(Some("RS256")), Algorithm::PS256));
+        assert!(key_allows(&jwk(None), Algorithm::ES384));
+    }
+
+    #[test]
+    fn reads_both_scope_claims() {
+        let claims = json!({ "scope": "mcp:read tool:list_tables", "scp": ["table:quotes"] });
+        assert_eq!(token_scopes(&claims), ["mcp:read", "tool:list_tables", "table:quotes"]);
+        assert_eq!(
+            scoped(&token_scopes(&claims), "tool:"),
+            Some(HashSet::from(["list_tables".to_string()]))
+        );
+        assert_eq!(scoped(&token_scopes(&claims), "role:"), None);
+    }
+}
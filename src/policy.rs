@@ -0,0 +1,510 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::RwLock,
+};
+use serde::Deserialize;
+use sqlx::Row;
+use tracing::info;
+use crate::auth::qualified;
+use crate::error::BridgeError;
+use crate::handlers::guard::{query_references, Wildcard};
+use crate::state::AppState;
+
+/// Fichier de politique d'accès (`MCP_PG_POLICY_FILE`). Les tables sont
+/// `schema.table` ou un nom seul pour `public`, les colonnes `table.colonne`.
+#[derive(Deserialize)]
+struct PolicyFile {
+    #[serde(default = "default_schemas")]
+    schemas: Vec<String>,
+    allow_tables: Option<Vec<String>>,
+    #[serde(default)]
+    deny_tables: Vec<String>,
+    #[serde(default)]
+    allow_columns: Vec<String>,
+    #[serde(default)]
+    deny_columns: Vec<String>,
+    #[serde(default)]
+    trusted_views: Vec<String>,
+}
+
+fn default_schemas() -> Vec<String> {
+    vec!["public".into()]
+}
+
+struct Rules {
+    schemas: Vec<String>,
+    allow_tables: Option<HashSet<String>>,
+    deny_tables: HashSet<String>,
+    /// Lister certaines colonnes d'une table masque les autres.
+    allow_columns: HashMap<String, HashSet<String>>,
+    deny_columns: HashMap<String, HashSet<String>>,
+    /// Vues qui n'exposent que ce qu'elles choisissent : pas vérifiées contre leurs sources.
+    trusted_views: HashSet<String>,
+}
+
+impl Rules {
+    fn denies_table(&self, table: &str) -> bool {
+        let schema = table.split_once('.').map_or("public", |(schema, _)| schema);
+        !self.schemas.iter().any(|s| s == schema)
+            || self.allow_tables.as_ref().is_some_and(|allowed| !allowed.contains(table))
+            || self.deny_tables.contains(table)
+    }
+
+    fn hides_column(&self, table: &str, column: &str) -> bool {
+        self.deny_columns.get(table).is_some_and(|denied| denied.contains(column))
+            || self.allow_columns.get(table).is_some_and(|allowed| !allowed.contains(column))
+    }
+}
+
+/// Ce à quoi la politique s'applique, lu dans le catalogue par `AccessPolicy::refresh`.
+#[derive(Default)]
+struct Catalog {
+    /// Colonnes de chaque relation des schémas visibles, par `schema.table`.
+    relations: HashMap<String, Vec<String>>,
+    /// Vues qui lisent une relation interdite ou une colonne masquée.
+    tainted: HashSet<String>,
+}
+
+impl Catalog {
+    /// Noms non qualifiés résolus comme avec le `search_path` par défaut : `pg_catalog` d'abord.
+    fn resolve(&self, name: &str) -> String {
+        if name.contains('.') {
+            return name.into();
+        }
+        match self.relations.contains_key(&format!("pg_catalog.{name}")) {
+            true => format!("pg_catalog.{name}"),
+            false => format!("public.{name}"),
+        }
+    }
+}
+
+/// Schémas, tables et colonnes visibles à travers le pont.
+///
+/// Sans `MCP_PG_POLICY_FILE`, tout ce que le rôle peut lire est visible. Sinon
+/// une relation est visible si son schéma est listé, qu'elle passe
+/// `allow_tables` / `deny_tables`, qu'elle existait au dernier rafraîchissement
+/// et, pour une vue, qu'elle ne lit ni relation interdite ni colonne masquée.
+#[derive(Default)]
+pub struct AccessPolicy {
+    rules: Option<Rules>,
+    catalog: RwLock<Catalog>,
+}
+
+impl AccessPolicy {
+    pub fn load(path: &Path) -> Result<Self, BridgeError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| BridgeError::Api(format!("Cannot read {}: {e}", path.display())))?;
+        Self::parse(&content, &path.display().to_string())
+    }
+
+    fn parse(content: &str, source: &str) -> Result<Self, BridgeError> {
+        let file: PolicyFile = toml::from_str(content)
+            .map_err(|e| BridgeError::Api(format!("Invalid policy file {source}: {e}")))?;
+
+        let tables = |names: &[String]| names.iter().map(|t| qualified(t)).collect::<HashSet<_>>();
+        let columns = |entries: &[String]| {
+            let mut columns: HashMap<String, HashSet<String>> = HashMap::new();
+            for entry in entries {
+                let (table, column) = entry.rsplit_once('.').ok_or_else(|| {
+                    BridgeError::Api(format!("Policy column '{entry}' must be written table.column"))
+                })?;
+                columns.entry(qualified(table)).or_default().insert(column.into());
+            }
+            Ok::<_, BridgeError>(columns)
+        };
+        let rules = Rules {
+            allow_tables: file.allow_tables.as_deref().map(tables),
+            deny_tables: tables(&file.deny_tables),
+            allow_columns: columns(&file.allow_columns)?,
+            deny_columns: columns(&file.deny_columns)?,
+            trusted_views: tables(&file.trusted_views),
+            schemas: file.schemas,
+        };
+        info!(
+            schemas = ?rules.schemas,
+            denied_tables = rules.deny_tables.len(),
+            restricted_tables = rules.allow_columns.len() + rules.deny_columns.len(),
+            "Access policy loaded"
+        );
+        Ok(Self { rules: Some(rules), catalog: RwLock::default() })
+    }
+
+    /// Relit les relations des schémas visibles et les dépendances de leurs vues.
+    /// Appelée à chaque rafraîchissement du schéma, avant la description.
+    pub async fn refresh(&self, state: &AppState) -> Result<(), BridgeError> {
+        let Some(rules) = &self.rules else {
+            return Ok(());
+        };
+        let mut tx = state.read_only_tx().await?;
+        let relation_rows = sqlx::query(
+            "SELECT n.nspname || '.' || c.relname AS relation, \
+                    ARRAY(SELECT a.attname::text FROM pg_attribute a \
+                          WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped \
+                          ORDER BY a.attnum) AS columns \
+             FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+             WHERE n.nspname::text = ANY($1) AND c.relkind IN ('r', 'p', 'v', 'm', 'f')",
+        )
+        .bind(&rules.schemas)
+        .fetch_all(&mut *tx)
+        .await?;
+        // Dépendances par colonne de la règle de réécriture de chaque vue
+        // (`refobjsubid = 0` quand elle lit des lignes entières).
+        let dependency_rows = sqlx::query(
+            "SELECT DISTINCT vn.nspname || '.' || v.relname AS view, \
+                    tn.nspname || '.' || t.relname AS source, a.attname::text AS column \
+             FROM pg_depend d \
+             JOIN pg_rewrite r ON r.oid = d.objid \
+             JOIN pg_class v ON v.oid = r.ev_class \
+             JOIN pg_namespace vn ON vn.oid = v.relnamespace \
+             JOIN pg_class t ON t.oid = d.refobjid \
+             JOIN pg_namespace tn ON tn.oid = t.relnamespace \
+             LEFT JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = d.refobjsubid \
+             WHERE d.classid = 'pg_rewrite'::regclass AND d.refclassid = 'pg_class'::regclass \
+               AND t.oid <> v.oid AND vn.nspname::text = ANY($1)",
+        )
+        .bind(&rules.schemas)
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let relations: HashMap<String, Vec<String>> = relation_rows
+            .iter()
+            .map(|row| {
+                (
+                    row.try_get("relation").unwrap_or_default(),
+                    row.try_get("columns").unwrap_or_default(),
+                )
+            })
+            .collect();
+        let dependencies: Vec<(String, String, Option<String>)> = dependency_rows
+            .iter()
+            .map(|row| {
+                (
+                    row.try_get("view").unwrap_or_default(),
+                    row.try_get("source").unwrap_or_default(),
+                    row.try_get("column").unwrap_or(None),
+                )
+            })
+            .collect();
+
+        let tainted = tainted_views(rules, &relations, &dependencies);
+        *self.catalog.write().unwrap() = Catalog { relations, tainted };
+        Ok(())
+    }
+
+    fn allows_relation(&self, rules: &Rules, catalog: &Catalog, relation: &str) -> bool {
+        !rules.denies_table(relation)
+            && catalog.relations.contains_key(relation)
+            && !catalog.tainted.contains(relation)
+    }
+
+    /// Schémas listés par `list_tables`, les ressources et la description du
+    /// schéma : ceux de la politique, `public` sans politique.
+    pub fn schemas(&self) -> Vec<String> {
+        self.rules.as_ref().map_or_else(default_schemas, |rules| rules.schemas.clone())
+    }
+
+    /// `table` est `schema.table`, ou un nom seul pour le schéma `public`.
+    pub fn allows_table(&self, table: &str) -> bool {
+        let Some(rules) = &self.rules else {
+            return true;
+        };
+        self.allows_relation(rules, &self.catalog.read().unwrap(), &qualified(table))
+    }
+
+    pub fn hides_column(&self, table: &str, column: &str) -> bool {
+        self.rules
+            .as_ref()
+            .is_some_and(|rules| rules.hides_column(&qualified(table), column))
+    }
+
+    /// Refuse une requête qui lit une relation masquée, ou qui pourrait lire une
+    /// colonne masquée : par son nom, avec `*`, une référence de ligne entière
+    /// (`to_json(a)`), des colonnes renommées (`accounts AS a(x, y)`) ou un `NATURAL JOIN`.
+    pub fn check_query(&self, sql: &str) -> Result<(), BridgeError> {
+        let Some(rules) = &self.rules else {
+            return Ok(());
+        };
+        let refs = query_references(sql)?;
+        let catalog = self.catalog.read().unwrap();
+
+        let denied: Vec<&str> = refs
+            .relations
+            .iter()
+            .filter(|name| !self.allows_relation(rules, &catalog, &catalog.resolve(name)))
+            .map(String::as_str)
+            .collect();
+        if !denied.is_empty() {
+            return Err(access_denied(format!("{} cannot be read", denied.join(", "))));
+        }
+
+        // Relations telles qu'écrites dans la requête, avec leurs colonnes masquées.
+        let restricted: BTreeMap<&str, BTreeSet<&str>> = refs
+            .relations
+            .iter()
+            .filter_map(|name| {
+                let relation = catalog.resolve(name);
+                let hidden: BTreeSet<&str> = catalog
+                    .relations
+                    .get(&relation)?
+                    .iter()
+                    .filter(|column| rules.hides_column(&relation, column))
+                    .map(String::as_str)
+                    .collect();
+                (!hidden.is_empty()).then_some((name.as_str(), hidden))
+            })
+            .collect();
+        let Some(&first) = restricted.keys().next() else {
+            return Ok(());
+        };
+        let designated = |qualifier: &str| -> Vec<&str> {
+            refs.aliases
+                .get(qualifier)
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .filter(|relation| restricted.contains_key(relation))
+                .collect()
+        };
+
+        for wildcard in &refs.wildcards {
+            let relation = match wildcard {
+                Wildcard::From(from) => from
+                    .iter()
+                    .map(String::as_str)
+                    .find(|relation| restricted.contains_key(relation)),
+                Wildcard::Of(qualifier) => designated(qualifier).first().copied(),
+            };
+            if let Some(relation) = relation {
+                return Err(access_denied(format!(
+                    "`*` would read the hidden columns of {relation}, list the columns instead"
+                )));
+            }
+        }
+        if let Some(relation) = refs.renamed.iter().find(|r| restricted.contains_key(r.as_str())) {
+            return Err(access_denied(format!(
+                "{relation} has hidden columns, its columns cannot be renamed by an alias"
+            )));
+        }
+        if refs.natural_join {
+            return Err(access_denied(format!(
+                "{first} has hidden columns, use JOIN ... ON instead of NATURAL JOIN"
+            )));
+        }
+        for (qualifier, column) in &refs.columns {
+            let targets = match qualifier {
+                Some(qualifier) => designated(qualifier),
+                None => {
+                    if let Some(relation) = designated(column).first() {
+                        return Err(access_denied(format!(
+                            "`{column}` reads whole rows of {relation}, which has hidden columns"
+                        )));
+                    }
+                    restricted.keys().copied().collect()
+                }
+            };
+            if let Some(relation) = targets.iter().find(|r| restricted[*r].contains(column.as_str())) {
+                return Err(access_denied(format!("column {relation}.{column} is hidden")));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Vues qui lisent, directement ou via d'autres vues, une relation interdite ou
+/// inconnue ou une colonne masquée ; `dependencies` sont des (vue, source, colonne).
+fn tainted_views(
+    rules: &Rules,
+    relations: &HashMap<String, Vec<String>>,
+    dependencies: &[(String, String, Option<String>)],
+) -> HashSet<String> {
+    // Vues de vues : on recommence jusqu'à ce qu'aucune nouvelle vue ne soit touchée.
+    let mut tainted = HashSet::new();
+    loop {
+        let before = tainted.len();
+        for (view, source, column) in dependencies {
+            if tainted.contains(view) || rules.trusted_views.contains(view) {
+                continue;
+            }
+            let reads_hidden = match column {
+                Some(column) => rules.hides_column(source, column),
+                None => relations
+                    .get(source)
+                    .is_some_and(|columns| columns.iter().any(|c| rules.hides_column(source, c))),
+            };
+            if reads_hidden
+                || rules.denies_table(source)
+                || !relations.contains_key(source)
+                || tainted.contains(source)
+            {
+                tainted.insert(view.clone());
+            }
+        }
+        if tainted.len() == before {
+            return tainted;
+        }
+    }
+}
+
+fn access_denied(reason: String) -> BridgeError {
+    BridgeError::Api(format!("Access denied: {reason}."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `accounts.iban` interdite, `holdings.avg_price` absente d'`allow_columns`,
+    /// `portfolio_snapshots` interdite ; des vues dessus, et `view_quotes` sur `quotes`.
+    fn policy() -> AccessPolicy {
+        let policy = AccessPolicy::parse(
+            r#"
+            deny_tables = ["portfolio_snapshots"]
+            deny_columns = ["accounts.iban"]
+            allow_columns = ["holdings.id", "holdings.account_id", "holdings.quantity"]
+            trusted_views = ["view_account_names"]
+            "#,
+            "test",
+        )
+        .unwrap();
+        let relations: HashMap<String, Vec<String>> = [
+            ("public.accounts", &["id", "name", "owner", "iban"][..]),
+            ("public.holdings", &["id", "account_id", "quantity", "avg_price"]),
+            ("public.quotes", &["isin", "date", "close"]),
+            ("public.portfolio_snapshots", &["owner", "total_value"]),
+            ("public.view_accounts", &["name", "iban"]),
+            ("public.view_of_view", &["name"]),
+            ("public.view_quotes", &["isin", "close"]),
+            ("public.view_snapshots", &["total_value"]),
+            ("public.view_holdings", &["account_id", "quantity"]),
+            ("public.view_account_names", &["name"]),
+            ("pg_catalog.pg_class", &["relname"]),
+        ]
+        .into_iter()
+        .map(|(relation, columns)| (relation.into(), columns.iter().map(|c| c.to_string()).collect()))
+        .collect();
+        let dependencies: Vec<(String, String, Option<String>)> = [
+            ("public.view_accounts", "public.accounts", Some("name")),
+            ("public.view_accounts", "public.accounts", Some("iban")),
+            ("public.view_of_view", "public.view_accounts", Some("name")),
+            ("public.view_quotes", "public.quotes", Some("isin")),
+            ("public.view_quotes", "public.quotes", Some("close")),
+            ("public.view_snapshots", "public.portfolio_snapshots", Some("total_value")),
+            ("public.view_holdings", "public.holdings", None),
+            ("public.view_account_names", "public.accounts", Some("iban")),
+        ]
+        .into_iter()
+        .map(|(view, source, column)| (view.into(), source.into(), column.map(String::from)))
+        .collect();
+        let tainted = tainted_views(policy.rules.as_ref().unwrap(), &relations, &dependencies);
+        *policy.catalog.write().unwrap() = Catalog { relations, tainted };
+        policy
+    }
+
+    fn denied(policy: &AccessPolicy, sql: &str) -> String {
+        match policy.check_query(sql) {
+            Ok(()) => panic!("`{sql}` was accepted"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_allowed_columns() {
+        let policy = policy();
+        for sql in [
+            "SELECT id, name, owner FROM accounts",
+            "SELECT a.name, h.quantity FROM accounts a JOIN holdings h ON h.account_id = a.id",
+            "SELECT * FROM quotes",
+            "SELECT q.* FROM quotes q JOIN accounts a ON a.name = q.isin",
+            "SELECT isin, close FROM view_quotes",
+            "SELECT name FROM view_account_names",
+        ] {
+            assert!(policy.check_query(sql).is_ok(), "`{sql}` was rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_hidden_columns() {
+        let policy = policy();
+        assert!(denied(&policy, "SELECT iban FROM accounts").contains("accounts.iban is hidden"));
+        assert!(denied(&policy, "SELECT a.iban FROM accounts a").contains("accounts.iban is hidden"));
+        assert!(denied(&policy, "SELECT upper(IBAN) FROM public.accounts").contains("is hidden"));
+        assert!(denied(&policy, "SELECT name FROM accounts WHERE iban LIKE 'FR%'").contains("is hidden"));
+        // Absente d'`allow_columns`.
+        assert!(denied(&policy, "SELECT avg_price FROM holdings").contains("holdings.avg_price is hidden"));
+    }
+
+    #[test]
+    fn rejects_wildcards_over_hidden_columns() {
+        let policy = policy();
+        for sql in [
+            "SELECT * FROM accounts",
+            "SELECT a.* FROM accounts a",
+            "SELECT q.close, a.* FROM quotes q, accounts a",
+            "SELECT * FROM holdings",
+        ] {
+            assert!(denied(&policy, sql).contains("`*`"), "`{sql}`");
+        }
+        assert!(denied(&policy, "SELECT to_json(a) FROM accounts a").contains("whole rows"));
+        assert!(denied(&policy, "SELECT x FROM accounts AS a(x, y, z, w)").contains("renamed"));
+        assert!(denied(&policy, "SELECT name FROM accounts NATURAL JOIN quotes").contains("NATURAL JOIN"));
+    }
+
+    #[test]
+    fn rejects_denied_relations() {
+        let policy = policy();
+        assert!(denied(&policy, "SELECT owner FROM portfolio_snapshots").contains("cannot be read"));
+        assert!(denied(&policy, "SELECT 1 FROM unknown_table").contains("cannot be read"));
+        // `pg_catalog` ne fait pas partie des `schemas` de la politique.
+        assert!(denied(&policy, "SELECT relname FROM pg_class").contains("pg_class cannot be read"));
+        assert!(!policy.allows_table("portfolio_snapshots"));
+        assert!(policy.allows_table("quotes"));
+    }
+
+    #[test]
+    fn rejects_views_over_denied_data() {
+        let policy = policy();
+        // Lit une colonne masquée, une table interdite, des lignes entières, ou via une vue.
+        for view in ["view_accounts", "view_of_view", "view_snapshots", "view_holdings"] {
+            assert!(!policy.allows_table(view), "{view} is readable");
+            assert!(denied(&policy, &format!("SELECT 1 FROM {view}")).contains("cannot be read"));
+        }
+        assert!(policy.allows_table("view_quotes"));
+        // De confiance, bien qu'elle lise `iban`.
+        assert!(policy.allows_table("view_account_names"));
+    }
+
+    #[test]
+    fn rejects_hidden_columns_behind_aliases() {
+        let policy = policy();
+        for sql in [
+            "WITH a AS (SELECT iban AS x FROM accounts) SELECT x FROM a",
+            "WITH a AS (SELECT * FROM accounts) SELECT name FROM a",
+            "SELECT x FROM (SELECT iban AS x FROM accounts) s",
+            "SELECT s.* FROM (SELECT * FROM accounts) s",
+            "SELECT name FROM accounts WHERE id IN (SELECT id FROM accounts WHERE iban IS NULL)",
+            "SELECT (SELECT max(iban) FROM accounts) AS x",
+            "SELECT q.close FROM quotes q WHERE EXISTS (SELECT 1 FROM accounts a WHERE a.iban = q.isin)",
+        ] {
+            assert!(policy.check_query(sql).is_err(), "`{sql}` was accepted");
+        }
+    }
+
+    #[test]
+    fn allows_everything_without_rules() {
+        let policy = AccessPolicy::default();
+        assert!(policy.check_query("SELECT * FROM accounts").is_ok());
+        assert!(policy.allows_table("portfolio_snapshots"));
+        assert!(!policy.hides_column("accounts", "iban"));
+    }
+
+    #[test]
+    fn lists_the_schemas_of_the_policy() {
+        assert_eq!(AccessPolicy::default().schemas(), ["public"]);
+        assert_eq!(policy().schemas(), ["public"]);
+        let policy = AccessPolicy::parse(r#"schemas = ["public", "market"]"#, "test").unwrap();
+        assert_eq!(policy.schemas(), ["public", "market"]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use serde_json::{json, Value};
+use crate::mcp::{McpNotification, ProtocolFeatures};
+
+/// Intervalle des notifications « toujours en cours » tant qu'une requête n'a
+/// pas renvoyé sa première ligne.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Canal vers le client d'une requête, fourni par le transport : le canal de
+/// la session SSE, le flux du `POST /mcp` (ou le flux `standalone`), ou stdout.
+#[derive(Clone)]
+pub struct Notifier(Arc<dyn Fn(String) + Send + Sync>);
+
+impl Notifier {
+    pub fn new(send: impl Fn(String) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(send))
+    }
+
+    /// Au mieux, comme tout message initié par le serveur.
+    pub fn send(&self, msg: String) {
+        (self.0)(msg)
+    }
+}
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Notifier")
+    }
+}
+
+/// `notifications/progress` pour le `_meta.progressToken` d'une requête.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    token: Value,
+    notifier: Notifier,
+    /// `progress` doit croître à chaque notification d'un même jeton.
+    step: Arc<AtomicU64>,
+    /// Les clients plus anciens n'ont que le compteur.
+    with_message: bool,
+}
+
+impl Progress {
+    /// `None` si le client n'a pas demandé de progression ou si le transport ne
+    /// peut rien envoyer avant la réponse.
+    pub fn from_params(
+        params: Option<&Value>,
+        notifier: Option<&Notifier>,
+        features: ProtocolFeatures,
+    ) -> Option<Self> {
+        let token = params?.get("_meta")?.get("progressToken")?;
+        if !(token.is_string() || token.is_i64() || token.is_u64()) {
+            return None;
+        }
+        Some(Self {
+            token: token.clone(),
+            notifier: notifier?.clone(),
+            step: Arc::default(),
+            with_message: features.progress_messages,
+        })
+    }
+
+    pub fn report(&self, message: impl Into<String>) {
+        let step = self.step.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut params = json!({ "progressToken": self.token, "progress": step });
+        if self.with_message {
+            params["message"] = message.into().into();
+        }
+        if let Ok(msg) = serde_json::to_string(&McpNotification::new("notifications/progress", Some(params))) {
+            self.notifier.send(msg);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use crate::audit::QueryTrace;
+use crate::auth::Principal;
+use crate::error::BridgeError;
+use crate::inflight::RequestHandle;
+use crate::mcp::ProtocolFeatures;
+use crate::progress::{Notifier, Progress};
+use crate::state::AppState;
+
+/// Ce qu'un outil sait de la requête qu'il sert.
+#[derive(Debug, Default, Clone)]
+pub struct CallContext {
+    /// Session SSE ou Streamable HTTP ; `None` en stdio et pour les requêtes sans état.
+    pub session_id: Option<String>,
+    /// Version du protocole négociée par `initialize`, si elle est connue.
+    pub protocol_version: Option<String>,
+    /// Fixé par `dispatch` pour les requêtes avec un id, afin de pouvoir annuler leur requête SQL.
+    pub request: Option<Arc<RequestHandle>>,
+    /// Comment le transport joint le client avant la réponse, s'il le peut.
+    pub notifier: Option<Notifier>,
+    /// Fixé par `dispatch` quand la requête porte un `_meta.progressToken`.
+    pub progress: Option<Progress>,
+    /// Jeton de la requête ; `None` sans authentification (et en stdio).
+    pub principal: Option<Arc<Principal>>,
+    /// Fixé par `dispatch` pour les appels d'outils : le SQL exécuté et son résultat, pour l'audit.
+    pub query: Option<Arc<QueryTrace>>,
+    /// Id d'une requête HTTP sans état ni principal : son annulation ne vise
+    /// qu'elle, puisque rien ne distingue ces appelants.
+    pub anonymous_request: Option<String>,
+}
+
+impl CallContext {
+    pub fn features(&self) -> ProtocolFeatures {
+        ProtocolFeatures::of(self.protocol_version.as_deref())
+    }
+
+    pub fn principal_name(&self) -> Option<&str> {
+        self.principal.as_ref().map(|p| p.name.as_str())
+    }
+
+    /// Un claim du jeton d'accès OAuth de l'appelant.
+    pub fn claim(&self, name: &str) -> Option<&Value> {
+        self.principal.as_ref()?.claims.as_ref()?.get(name)
+    }
+
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.principal.as_ref().is_none_or(|p| p.allows_tool(tool))
+    }
+
+    pub fn allows_table(&self, table: &str) -> bool {
+        self.principal.as_ref().is_none_or(|p| p.allows_table(table))
+    }
+
+    /// Envoie une `notifications/progress` si le client en a demandé.
+    pub fn progress(&self, message: impl Into<String>) {
+        if let Some(progress) = &self.progress {
+            progress.report(message);
+        }
+    }
+}
+
+/// Résultat d'un outil : le texte affiché au modèle et, pour les outils qui
+/// déclarent un `outputSchema`, la même donnée en JSON (`structuredContent`).
+pub struct ToolOutput {
+    pub text: String,
+    pub structured: Option<Value>,
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self { text, structured: None }
+    }
+}
+
+/// Un outil MCP : nom, description, schéma d'entrée et exécution.
+/// Tous les transports passent par le `ToolRegistry` d'`AppState`.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+    /// Nom lisible affiché par les clients (protocole 2025-06-18).
+    fn title(&self) -> Option<String> {
+        None
+    }
+    /// Peut dépendre de l'appelant, par ex. pour ne décrire que ses tables.
+    fn description(&self, ctx: &CallContext) -> String;
+    fn input_schema(&self) -> Value;
+    /// Schéma de `structuredContent`, annoncé aux clients qui le supportent.
+    fn output_schema(&self) -> Option<Value> {
+        None
+    }
+    /// Indices de comportement (protocole 2025-03-26). Tous les outils du pont ne font que lire.
+    fn annotations(&self) -> Value {
+        json!({
+            "readOnlyHint": true,
+            "destructiveHint": false,
+            "idempotentHint": true,
+            "openWorldHint": false,
+        })
+    }
+    async fn call(
+        &self,
+        state: &AppState,
+        ctx: &CallContext,
+        args: &Value,
+    ) -> Result<ToolOutput, BridgeError>;
+}
+
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// Enregistre un outil ; il remplace un outil de même nom.
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name).cloned()
+    }
+
+    /// Corps de la réponse à `tools/list` : les outils que l'appelant peut utiliser,
+    /// avec les champs que permet sa version du protocole.
+    pub fn list(&self, ctx: &CallContext) -> Value {
+        let features = ctx.features();
+        let tools: Vec<Value> = self
+            .tools
+            .iter()
+            .filter(|t| ctx.allows_tool(t.name()))
+            .map(|t| {
+                let mut tool = json!({
+                    "name": t.name(),
+                    "description": t.description(ctx),
+                    "inputSchema": t.input_schema(),
+                });
+                if features.titles && let Some(title) = t.title() {
+                    tool["title"] = title.into();
+                }
+                if features.tool_annotations {
+                    tool["annotations"] = t.annotations();
+                }
+                if features.structured_output && let Some(schema) = t.output_schema() {
+                    tool["outputSchema"] = schema;
+                }
+                tool
+            })
+            .collect();
+        json!({ "tools": tools })
+    }
+}
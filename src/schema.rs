@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, sync::{Arc, RwLock}, time::Duration};
+use sqlx::Row;
+use tracing::{info, warn};
+use crate::auth::unqualified;
+use crate::error::BridgeError;
+use crate::state::AppState;
+
+/// Description du schéma générée depuis le catalogue PostgreSQL.
+///
+/// Partagée avec l'outil `sql_read_query`, dont la description l'embarque, et
+/// rafraîchie en tâche de fond par `refresh_loop`.
+pub struct LiveSchema {
+    preamble: Option<String>,
+    /// Une ligne rendue par relation, indexée par nom.
+    tables: RwLock<BTreeMap<String, String>>,
+}
+
+impl LiveSchema {
+    pub fn new(preamble: Option<String>) -> Self {
+        Self { preamble, tables: RwLock::default() }
+    }
+
+    pub fn preamble(&self) -> Option<&str> {
+        self.preamble.as_deref()
+    }
+
+    /// La description des relations que `visible` accepte.
+    pub fn text(&self, visible: impl Fn(&str) -> bool) -> String {
+        self.tables
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| visible(name))
+            .map(|(_, line)| line.as_str())
+            .collect()
+    }
+
+    /// Remplace la description ; renvoie `true` si elle a changé.
+    fn set(&self, tables: BTreeMap<String, String>) -> bool {
+        let mut current = self.tables.write().unwrap();
+        if *current == tables {
+            return false;
+        }
+        *current = tables;
+        true
+    }
+}
+
+struct Column {
+    name: String,
+    data_type: String,
+    enum_values: Option<String>,
+    comment: Option<String>,
+    references: Option<String>,
+}
+
+struct Table {
+    kind: &'static str,
+    comment: Option<String>,
+    columns: Vec<Column>,
+    /// Clés étrangères multi-colonnes, rendues après les colonnes.
+    foreign_keys: Vec<String>,
+}
+
+/// Lit tables, vues, colonnes, commentaires, valeurs d'enum et clés étrangères
+/// des schémas de la politique (`public` sans politique) et les rend à raison
+/// d'une table par ligne, par ex.
+/// `holdings(id integer, account_id integer -> accounts.id, ...) /* comment */`.
+/// Les relations hors de `public` sont nommées `schema.table`.
+pub async fn describe_schema(state: &AppState) -> Result<BTreeMap<String, String>, BridgeError> {
+    let schemas = state.policy.schemas();
+    let mut tx = state.read_only_tx().await?;
+
+    let table_rows = sqlx::query(
+        "SELECT n.nspname || '.' || c.relname AS name, c.relkind::text AS kind, \
+                obj_description(c.oid, 'pg_class') AS comment \
+         FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE n.nspname::text = ANY($1) AND c.relkind IN ('r', 'p', 'v', 'm', 'f') \
+         ORDER BY n.nspname, c.relname",
+    )
+    .bind(&schemas)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let column_rows = sqlx::query(
+        "SELECT n.nspname || '.' || c.relname AS table_name, a.attname::text AS name, \
+                format_type(a.atttypid, a.atttypmod) AS data_type, \
+                col_description(c.oid, a.attnum) AS comment, \
+                (SELECT string_agg(e.enumlabel::text, '|' ORDER BY e.enumsortorder) \
+                 FROM pg_enum e WHERE e.enumtypid = a.atttypid) AS enum_values \
+         FROM pg_attribute a \
+         JOIN pg_class c ON c.oid = a.attrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE n.nspname::text = ANY($1) AND c.relkind IN ('r', 'p', 'v', 'm', 'f') \
+           AND a.attnum > 0 AND NOT a.attisdropped \
+         ORDER BY n.nspname, c.relname, a.attnum",
+    )
+    .bind(&schemas)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let fk_rows = sqlx::query(
+        "SELECT n.nspname || '.' || src.relname AS table_name, \
+                dn.nspname || '.' || dst.relname AS ref_table, \
+                ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY k(num, ord) \
+                      JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.num \
+                      ORDER BY k.ord) AS columns, \
+                ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY k(num, ord) \
+                      JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.num \
+                      ORDER BY k.ord) AS ref_columns \
+         FROM pg_constraint con \
+         JOIN pg_class src ON src.oid = con.conrelid \
+         JOIN pg_class dst ON dst.oid = con.confrelid \
+         JOIN pg_namespace n ON n.oid = src.relnamespace \
+         JOIN pg_namespace dn ON dn.oid = dst.relnamespace \
+         WHERE con.contype = 'f' AND n.nspname::text = ANY($1) \
+         ORDER BY n.nspname, src.relname, con.conname",
+    )
+    .bind(&schemas)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let policy = &state.policy;
+    let mut tables: BTreeMap<String, Table> = table_rows
+        .iter()
+        .filter(|row| policy.allows_table(row.try_get("name").unwrap_or_default()))
+        .map(|row| {
+            let name: String = row.try_get("name").unwrap_or_default();
+            let name = unqualified(&name).to_string();
+            let kind: String = row.try_get("kind").unwrap_or_default();
+            let table = Table {
+                kind: match kind.as_str() {
+                    "v" => "view",
+                    "m" => "materialized view",
+                    _ => "table",
+                },
+                comment: row.try_get("comment").unwrap_or(None),
+                columns: Vec::new(),
+                foreign_keys: Vec::new(),
+            };
+            (name, table)
+        })
+        .collect();
+
+    for row in &column_rows {
+        let table_name: String = row.try_get("table_name").unwrap_or_default();
+        let name: String = row.try_get("name").unwrap_or_default();
+        if policy.hides_column(&table_name, &name) {
+            continue;
+        }
+        if let Some(table) = tables.get_mut(unqualified(&table_name)) {
+            table.columns.push(Column {
+                name,
+                data_type: row.try_get("data_type").unwrap_or_default(),
+                enum_values: row.try_get("enum_values").unwrap_or(None),
+                comment: row.try_get("comment").unwrap_or(None),
+                references: None,
+            });
+        }
+    }
+
+    for row in &fk_rows {
+        let table_name: String = row.try_get("table_name").unwrap_or_default();
+        let ref_table: String = row.try_get("ref_table").unwrap_or_default();
+        let columns: Vec<String> = row.try_get("columns").unwrap_or_default();
+        let ref_columns: Vec<String> = row.try_get("ref_columns").unwrap_or_default();
+        let Some(table) = tables.get_mut(unqualified(&table_name)) else { continue };
+        // Les colonnes et relations masquées restent aussi hors des clés.
+        if !policy.allows_table(&ref_table)
+            || columns.iter().any(|c| policy.hides_column(&table_name, c))
+            || ref_columns.iter().any(|c| policy.hides_column(&ref_table, c))
+        {
+            continue;
+        }
+        let ref_table = unqualified(&ref_table);
+        match (columns.as_slice(), ref_columns.as_slice()) {
+            ([col], [ref_col]) => {
+                if let Some(column) = table.columns.iter_mut().find(|c| &c.name == col) {
+                    column.references = Some(format!("{ref_table}.{ref_col}"));
+                }
+            }
+            _ => table.foreign_keys.push(format!(
+                "({}) -> {ref_table}({})",
+                columns.join(", "),
+                ref_columns.join(", ")
+            )),
+        }
+    }
+
+    Ok(render(&tables))
+}
+
+fn render(tables: &BTreeMap<String, Table>) -> BTreeMap<String, String> {
+    let mut lines = BTreeMap::new();
+    for (name, table) in tables {
+        let mut parts: Vec<String> = table
+            .columns
+            .iter()
+            .map(|c| {
+                let mut col = format!("{} {}", c.name, c.data_type);
+                if let Some(values) = &c.enum_values {
+                    col.push_str(&format!("[{values}]"));
+                }
+                if let Some(target) = &c.references {
+                    col.push_str(&format!(" -> {target}"));
+                }
+                if let Some(comment) = &c.comment {
+                    col.push_str(&format!(" /* {comment} */"));
+                }
+                col
+            })
+            .collect();
+        parts.extend(table.foreign_keys.iter().map(|fk| format!("FOREIGN KEY {fk}")));
+
+        let mut out = format!("{name}({})", parts.join(", "));
+        if table.kind != "table" {
+            out.push_str(&format!(" [{}]", table.kind));
+        }
+        if let Some(comment) = &table.comment {
+            out.push_str(&format!(" /* {comment} */"));
+        }
+        out.push_str(";\n");
+        lines.insert(name.clone(), out);
+    }
+    lines
+}
+
+/// Régénère le catalogue de la politique d'accès, puis la description ; renvoie
+/// `true` si la description a changé.
+pub async fn refresh(state: &AppState) -> Result<bool, BridgeError> {
+    state.policy.refresh(state).await?;
+    let tables = describe_schema(state).await?;
+    Ok(state.schema.set(tables))
+}
+
+/// Rafraîchit périodiquement la description et notifie les clients
+/// (`notifications/tools/list_changed`) quand elle change.
+pub async fn refresh_loop(state: Arc<AppState>, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match refresh(&state).await {
+            Ok(true) => {
+                info!("Database schema changed, notifying clients");
+                state.notify_all("notifications/tools/list_changed");
+            }
+            Ok(false) => {}
+            Err(e) => warn!(error = %e, "Schema refresh failed"),
+        }
+    }
+}
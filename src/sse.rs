@@ -0,0 +1,178 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use axum::{
+    body::Bytes,
+    extract::{Extension, Query, State},
+    http::StatusCode,
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse},
+    Json,
+};
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::auth::Principal;
+use crate::dispatch;
+use crate::mcp::McpRequest;
+use crate::progress::Notifier;
+use crate::registry::CallContext;
+use crate::state::AppState;
+
+/// Sessions du transport SSE historique (`GET /sse` + `POST /messages`).
+///
+/// Chaque flux `GET /sse` a son identifiant de session et son canal ; les
+/// réponses à `POST /messages?sessionId=...` ne partent que sur ce flux.
+#[derive(Default)]
+pub struct SseSessions {
+    sessions: Mutex<HashMap<String, SseSession>>,
+}
+
+#[derive(Clone)]
+struct SseSession {
+    tx: mpsc::Sender<String>,
+    /// Fixée par `initialize`.
+    protocol_version: Option<String>,
+    /// Jeton qui a ouvert le flux : lui seul peut poster dans la session.
+    owner: Option<String>,
+}
+
+impl SseSessions {
+    fn open(&self, owner: Option<String>) -> (String, mpsc::Receiver<String>) {
+        let id = Uuid::new_v4().to_string();
+        let (tx, rx) = mpsc::channel(100);
+        let session = SseSession { tx, protocol_version: None, owner };
+        self.sessions.lock().unwrap().insert(id.clone(), session);
+        (id, rx)
+    }
+
+    fn close(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+
+    /// Une session ouverte avec un autre jeton est signalée inconnue.
+    fn get(&self, id: &str, owner: Option<&str>) -> Option<SseSession> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(id).filter(|s| s.owner.as_deref() == owner).cloned()
+    }
+
+    fn set_protocol_version(&self, id: &str, version: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(id) {
+            session.protocol_version = Some(version.into());
+        }
+    }
+
+    /// Au mieux : une session dont le canal est plein manque le message.
+    pub fn send_all(&self, msg: &str) {
+        for session in self.sessions.lock().unwrap().values() {
+            let _ = session.tx.try_send(msg.to_string());
+        }
+    }
+}
+
+/// Retire la session quand axum abandonne le flux (client déconnecté).
+struct SessionGuard {
+    state: Arc<AppState>,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.state.sse_sessions.close(&self.id);
+        self.state.in_flight.cancel_session(&self.id);
+        self.state.cursors.close_session(&self.id);
+        info!(session = %self.id, "SSE session closed");
+    }
+}
+
+pub async fn sse_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Arc<Principal>>>,
+) -> Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>> {
+    let owner = principal.map(|Extension(p)| p.name.clone());
+    let (id, rx) = state.sse_sessions.open(owner);
+    info!(session = %id, "SSE session opened");
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/messages?sessionId={id}"));
+    let guard = SessionGuard { state, id };
+
+    let messages = stream::unfold((rx, guard), |(mut rx, guard)| async move {
+        let msg = rx.recv().await?;
+        Some((Ok(Event::default().event("message").data(msg)), (rx, guard)))
+    });
+    let stream = futures::StreamExt::chain(stream::once(async { Ok(endpoint) }), messages);
+    Sse::new(stream).keep_alive(KeepAlive::new())
+}
+
+#[derive(Deserialize)]
+pub struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
+pub async fn messages_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SessionQuery>,
+    principal: Option<Extension<Arc<Principal>>>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let principal = principal.map(|Extension(p)| p);
+    let owner = principal.as_ref().map(|p| p.name.as_str());
+    let payload = match McpRequest::parse(&body) {
+        Ok(payload) => payload,
+        Err(response) => return (StatusCode::BAD_REQUEST, Json(response)).into_response(),
+    };
+    let method = payload.method.clone();
+    // Sans `sessionId`, la réponse pourrait partir sur le flux d'un autre client.
+    let session = query
+        .session_id
+        .as_ref()
+        .and_then(|id| state.sse_sessions.get(id, owner).map(|s| (id.clone(), s)));
+
+    if let Some(version) = payload.negotiated_protocol_version() {
+        info!("Handling 'initialize' via direct HTTP response");
+        if let Some((id, _)) = &session {
+            state.sse_sessions.set_protocol_version(id, version);
+        }
+        return match dispatch::handle_request(&state, &CallContext::default(), payload).await {
+            Some(response) => (StatusCode::OK, Json(response)).into_response(),
+            None => StatusCode::ACCEPTED.into_response(),
+        };
+    }
+
+    let Some((session_id, session)) = session else {
+        return match query.session_id {
+            Some(id) => (StatusCode::NOT_FOUND, format!("Unknown SSE session: {id}")),
+            None => (StatusCode::BAD_REQUEST, "Missing sessionId query parameter".into()),
+        }
+        .into_response();
+    };
+
+    let tx = session.tx;
+    let notifier = Notifier::new({
+        let tx = tx.clone();
+        move |msg| {
+            let _ = tx.try_send(msg);
+        }
+    });
+    let ctx = CallContext {
+        session_id: Some(session_id),
+        protocol_version: session.protocol_version,
+        notifier: Some(notifier),
+        principal,
+        ..CallContext::default()
+    };
+    tokio::spawn(async move {
+        let Some(response) = dispatch::handle_request(&state, &ctx, payload).await else {
+            return;
+        };
+        if let Ok(json_msg) = serde_json::to_string(&response)
+            && tx.send(json_msg).await.is_err()
+        {
+            warn!("Could not deliver {method} via SSE (session closed)");
+        }
+    });
+
+    StatusCode::ACCEPTED.into_response()
+}
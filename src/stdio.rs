@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
+use crate::dispatch;
+use crate::mcp::McpRequest;
+use crate::progress::Notifier;
+use crate::registry::CallContext;
+use crate::state::AppState;
+
+/// Transport stdio : JSON-RPC délimité par des retours à la ligne.
+///
+/// Chaque requête est traitée dans sa propre tâche ; une seule tâche écrit sur
+/// stdout, les réponses ne s'entremêlent donc jamais. Les logs vont sur stderr.
+pub async fn serve(state: Arc<AppState>) {
+    info!("MCP PostgreSQL Bridge listening on stdio");
+    let (out_tx, mut out_rx) = mpsc::channel::<String>(100);
+
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = out_rx.recv().await {
+            if stdout.write_all(line.as_bytes()).await.is_err()
+                || stdout.write_all(b"\n").await.is_err()
+                || stdout.flush().await.is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut notifications = state.notifications.subscribe();
+    let notify_tx = out_tx.clone();
+    let forwarder = tokio::spawn(async move {
+        loop {
+            let msg = match notifications.recv().await {
+                Ok(msg) => msg,
+                // Une notification manquée n'est qu'un changement de liste : la suivante suffit.
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "stdio notification forwarder lagged");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if notify_tx.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut ctx = CallContext::default();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let payload = match McpRequest::parse(line.as_bytes()) {
+            Ok(payload) => payload,
+            Err(response) => {
+                warn!("Malformed JSON-RPC message on stdin");
+                if let Ok(json_msg) = serde_json::to_string(&response) {
+                    let _ = out_tx.send(json_msg).await;
+                }
+                continue;
+            }
+        };
+        if let Some(version) = payload.negotiated_protocol_version() {
+            ctx.protocol_version = Some(version.into());
+        }
+        let state = state.clone();
+        let out_tx = out_tx.clone();
+        let ctx = CallContext {
+            notifier: Some(Notifier::new({
+                let out_tx = out_tx.clone();
+                move |msg| {
+                    let _ = out_tx.try_send(msg);
+                }
+            })),
+            ..ctx.clone()
+        };
+        tokio::spawn(async move {
+            if let Some(response) = dispatch::handle_request(&state, &ctx, payload).await
+                && let Ok(json_msg) = serde_json::to_string(&response)
+            {
+                let _ = out_tx.send(json_msg).await;
+            }
+        });
+    }
+
+    info!("stdin closed, shutting down");
+    // Le relais détient aussi un émetteur : on l'arrête pour que le canal se ferme.
+    forwarder.abort();
+    drop(out_tx);
+    let _ = writer.await;
+}
@@ -0,0 +1,542 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use axum::{
+    body::Bytes,
+    extract::{Extension, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    Json,
+};
+use futures::stream::{self, Stream};
+use tokio::sync::broadcast;
+use tracing::info;
+use uuid::Uuid;
+use crate::auth::Principal;
+use crate::dispatch;
+use crate::mcp::{is_supported_protocol_version, McpRequest, DEFAULT_HTTP_PROTOCOL_VERSION};
+use crate::progress::Notifier;
+use crate::registry::CallContext;
+use crate::state::AppState;
+
+pub const SESSION_HEADER: &str = "mcp-session-id";
+/// Envoyé par les clients à chaque requête après `initialize` (depuis 2025-06-18).
+pub const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// Les sessions jamais fermées par un `DELETE` expirent après cette inactivité,
+/// sauf si un flux SSE est encore ouvert sur elles.
+const SESSION_IDLE_TTL: Duration = Duration::from_secs(3600);
+/// Clé du flux autonome `GET /mcp`.
+const STANDALONE_STREAM: &str = "standalone";
+
+#[derive(Clone, Debug)]
+struct StoredEvent {
+    id: u64,
+    stream: String,
+    /// `None` : fin du flux sans message, pour une requête annulée.
+    data: Option<String>,
+    /// Dernier événement du flux d'une réponse POST : le flux se termine après lui.
+    last: bool,
+}
+
+/// Session du transport Streamable HTTP, créée par `initialize`.
+///
+/// Chaque événement SSE passe par `push`, qui le numérote, le garde dans un
+/// journal borné pour `Last-Event-ID` et le diffuse aux flux ouverts. Il porte
+/// la clé de son flux (`standalone` pour `GET /mcp`, un UUID par réponse POST).
+pub struct McpSession {
+    pub id: String,
+    /// Négociée par `initialize`.
+    protocol_version: OnceLock<String>,
+    /// Jeton qui a créé la session : les autres jetons reçoivent `404`.
+    owner: Option<String>,
+    log: Mutex<EventLog>,
+    /// Taille maximale du journal, en octets de messages.
+    replay_bytes: usize,
+    live: broadcast::Sender<StoredEvent>,
+}
+
+struct EventLog {
+    next_id: u64,
+    events: VecDeque<StoredEvent>,
+    /// Somme des `StoredEvent::size` des événements journalisés.
+    bytes: usize,
+    last_seen: Instant,
+}
+
+impl StoredEvent {
+    fn size(&self) -> usize {
+        self.stream.len() + self.data.as_ref().map_or(0, String::len)
+    }
+}
+
+impl McpSession {
+    fn new(owner: Option<String>, replay_bytes: usize) -> Self {
+        let (live, _) = broadcast::channel(256);
+        Self {
+            id: Uuid::new_v4().to_string(),
+            protocol_version: OnceLock::new(),
+            owner,
+            log: Mutex::new(EventLog {
+                next_id: 1,
+                events: VecDeque::new(),
+                bytes: 0,
+                last_seen: Instant::now(),
+            }),
+            replay_bytes,
+            live,
+        }
+    }
+
+    fn touch(&self) {
+        self.log.lock().unwrap().last_seen = Instant::now();
+    }
+
+    /// Inactive depuis `ttl` et sans flux ouvert pour la recevoir.
+    fn expired(&self, ttl: Duration) -> bool {
+        self.live.receiver_count() == 0 && self.log.lock().unwrap().last_seen.elapsed() >= ttl
+    }
+
+    fn push(&self, stream: &str, data: Option<String>, last: bool) {
+        let mut log = self.log.lock().unwrap();
+        let event = StoredEvent { id: log.next_id, stream: stream.into(), data, last };
+        log.next_id += 1;
+        log.bytes += event.size();
+        log.events.push_back(event.clone());
+        // Le plus ancien part d'abord ; le dernier reste, même plus gros que la limite.
+        while log.bytes > self.replay_bytes && log.events.len() > 1 {
+            if let Some(old) = log.events.pop_front() {
+                log.bytes -= old.size();
+            }
+        }
+        // Envoyé sous le verrou : les abonnés voient les événements dans l'ordre.
+        let _ = self.live.send(event);
+    }
+
+    /// S'abonne à `stream` en rejouant les événements journalisés après `after`.
+    fn subscribe(&self, stream: String, after: Option<u64>) -> EventStream {
+        let log = self.log.lock().unwrap();
+        let rx = self.live.subscribe();
+        let replay: VecDeque<StoredEvent> = match after {
+            Some(after) => log
+                .events
+                .iter()
+                .filter(|e| e.stream == stream && e.id > after)
+                .cloned()
+                .collect(),
+            None => VecDeque::new(),
+        };
+        // Un flux POST terminé, repris après son dernier événement : plus rien à envoyer.
+        let done = replay.is_empty()
+            && after.is_some()
+            && log.events.iter().any(|e| e.stream == stream && e.last);
+        EventStream { replay, rx, stream, high_water: log.next_id - 1, done }
+    }
+
+    /// Flux auquel appartient un identifiant d'événement encore journalisé.
+    fn stream_of(&self, event_id: u64) -> Option<String> {
+        let log = self.log.lock().unwrap();
+        log.events.iter().find(|e| e.id == event_id).map(|e| e.stream.clone())
+    }
+}
+
+struct EventStream {
+    replay: VecDeque<StoredEvent>,
+    rx: broadcast::Receiver<StoredEvent>,
+    stream: String,
+    /// Dernier identifiant déjà journalisé à l'abonnement : les copies en direct
+    /// antérieures sont ignorées.
+    high_water: u64,
+    done: bool,
+}
+
+impl EventStream {
+    fn into_sse(self) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(self, |mut s| async move {
+            if s.done {
+                return None;
+            }
+            let event = match s.replay.pop_front() {
+                Some(event) => event,
+                None => loop {
+                    match s.rx.recv().await {
+                        Ok(e) if e.stream == s.stream && e.id > s.high_water => break e,
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                },
+            };
+            s.done = event.last;
+            let sse = Event::default()
+                .id(event.id.to_string())
+                .event("message")
+                .data(event.data?);
+            Some((Ok(sse), s))
+        })
+    }
+}
+
+pub struct McpSessions {
+    sessions: Mutex<HashMap<String, Arc<McpSession>>>,
+    /// `MCP_PG_MAX_SESSIONS` : au-delà, `initialize` reçoit `503`.
+    max_sessions: usize,
+    /// `MCP_PG_SESSION_REPLAY_BYTES` : journal `Last-Event-ID` de chaque session.
+    replay_bytes: usize,
+    idle_ttl: Duration,
+}
+
+impl McpSessions {
+    pub fn new(max_sessions: usize, replay_bytes: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions,
+            replay_bytes,
+            idle_ttl: SESSION_IDLE_TTL,
+        }
+    }
+
+    /// `None` quand `max_sessions` sessions sont déjà ouvertes.
+    fn create(&self, owner: Option<String>) -> Option<Arc<McpSession>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= self.max_sessions {
+            return None;
+        }
+        let session = Arc::new(McpSession::new(owner, self.replay_bytes));
+        sessions.insert(session.id.clone(), session.clone());
+        Some(session)
+    }
+
+    fn get(&self, id: &str, owner: Option<&str>) -> Option<Arc<McpSession>> {
+        let session = self.sessions.lock().unwrap().get(id).cloned()?;
+        if session.owner.as_deref() != owner || session.expired(self.idle_ttl) {
+            return None;
+        }
+        session.touch();
+        Some(session)
+    }
+
+    fn remove(&self, id: &str, owner: Option<&str>) -> Option<Arc<McpSession>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(id)?.owner.as_deref() != owner {
+            return None;
+        }
+        sessions.remove(id)
+    }
+
+    /// Retire les sessions expirées et renvoie leurs identifiants.
+    pub fn sweep(&self) -> Vec<String> {
+        let mut expired = Vec::new();
+        self.sessions.lock().unwrap().retain(|id, s| {
+            let keep = !s.expired(self.idle_ttl);
+            if !keep {
+                expired.push(id.clone());
+            }
+            keep
+        });
+        expired
+    }
+
+    /// Pousse un message du serveur sur le flux autonome de chaque session.
+    pub fn send_all(&self, msg: &str) {
+        for session in self.sessions.lock().unwrap().values() {
+            session.push(STANDALONE_STREAM, Some(msg.to_string()), false);
+        }
+    }
+}
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get(SESSION_HEADER).and_then(|v| v.to_str().ok())
+}
+
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"))
+}
+
+fn session_not_found() -> Response {
+    (StatusCode::NOT_FOUND, "Unknown or expired Mcp-Session-Id").into_response()
+}
+
+/// Libère ce que la session `id` retenait : requêtes en cours et résultats paginés.
+fn end_session(state: &AppState, id: &str) {
+    state.in_flight.cancel_session(id);
+    state.cursors.close_session(id);
+}
+
+/// Termine les sessions expirées ; appelé par `AppState::sweep_loop` et avant
+/// chaque `initialize`, pour que les sessions abandonnées ne bloquent pas la limite.
+pub fn sweep_sessions(state: &AppState) {
+    for id in state.mcp_sessions.sweep() {
+        end_session(state, &id);
+        info!(session = %id, "Streamable HTTP session expired");
+    }
+}
+
+/// `POST /mcp` — une requête, notification ou réponse JSON-RPC.
+///
+/// `initialize` crée une session renvoyée dans `Mcp-Session-Id`. Sans cet
+/// en-tête, la requête est servie sans état. Dans une session, `tools/call`
+/// répond en flux SSE si le client l'accepte.
+pub async fn mcp_post(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Arc<Principal>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let principal = principal.map(|Extension(p)| p);
+    let owner = principal.as_ref().map(|p| p.name.as_str());
+    let payload = match McpRequest::parse(&body) {
+        Ok(payload) => payload,
+        Err(response) => return (StatusCode::BAD_REQUEST, Json(response)).into_response(),
+    };
+
+    if let Some(version) = payload.negotiated_protocol_version() {
+        sweep_sessions(&state);
+        let Some(session) = state.mcp_sessions.create(owner.map(String::from)) else {
+            return (StatusCode::SERVICE_UNAVAILABLE, "Too many Streamable HTTP sessions")
+                .into_response();
+        };
+        let _ = session.protocol_version.set(version.into());
+        info!(session = %session.id, version, "Streamable HTTP session created");
+        let ctx = CallContext {
+            session_id: Some(session.id.clone()),
+            protocol_version: Some(version.into()),
+            principal,
+            ..CallContext::default()
+        };
+        let Some(response) = dispatch::handle_request(&state, &ctx, payload).await else {
+            return StatusCode::ACCEPTED.into_response();
+        };
+        let mut res = Json(response).into_response();
+        if let Ok(value) = HeaderValue::from_str(&session.id) {
+            res.headers_mut().insert(SESSION_HEADER, value);
+        }
+        return res;
+    }
+
+    let session = match session_id(&headers) {
+        Some(id) => match state.mcp_sessions.get(id, owner) {
+            Some(session) => Some(session),
+            None => return session_not_found(),
+        },
+        None => None,
+    };
+    let header_version = headers
+        .get(PROTOCOL_VERSION_HEADER)
+        .map(|v| v.to_str().unwrap_or_default());
+    if let Some(version) = header_version
+        && !is_supported_protocol_version(version)
+    {
+        return (StatusCode::BAD_REQUEST, format!("Unsupported MCP-Protocol-Version: {version}"))
+            .into_response();
+    }
+    // Sans session, seul l'en-tête indique la version du protocole.
+    let protocol_version = session
+        .as_ref()
+        .and_then(|s| s.protocol_version.get().cloned())
+        .or_else(|| header_version.map(String::from))
+        .unwrap_or_else(|| DEFAULT_HTTP_PROTOCOL_VERSION.into());
+    // Sans session, rien ne peut joindre le client avant la réponse.
+    let notifier = session.clone().map(|session| {
+        Notifier::new(move |msg| session.push(STANDALONE_STREAM, Some(msg), false))
+    });
+    let anonymous_request =
+        (session.is_none() && principal.is_none()).then(|| Uuid::new_v4().to_string());
+    let mut ctx = CallContext {
+        session_id: session.as_ref().map(|s| s.id.clone()),
+        protocol_version: Some(protocol_version),
+        notifier,
+        principal,
+        anonymous_request,
+        ..CallContext::default()
+    };
+
+    if payload.id.is_none() {
+        dispatch::handle_request(&state, &ctx, payload).await;
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    match session {
+        Some(session) if payload.method == "tools/call" && accepts_event_stream(&headers) => {
+            let stream_key = Uuid::new_v4().to_string();
+            let events = session.subscribe(stream_key.clone(), None);
+            // La progression d'un appel en flux passe sur son propre flux, avant la réponse.
+            ctx.notifier = Some(Notifier::new({
+                let session = session.clone();
+                let stream_key = stream_key.clone();
+                move |msg| session.push(&stream_key, Some(msg), false)
+            }));
+            tokio::spawn(async move {
+                // Une requête annulée n'a pas de réponse, mais son flux se ferme quand même.
+                let response = dispatch::handle_request(&state, &ctx, payload).await;
+                let data = response.and_then(|response| serde_json::to_string(&response).ok());
+                session.push(&stream_key, data, true);
+            });
+            Sse::new(events.into_sse())
+                .keep_alive(KeepAlive::new())
+                .into_response()
+        }
+        _ => match dispatch::handle_request(&state, &ctx, payload).await {
+            Some(response) => Json(response).into_response(),
+            None => StatusCode::ACCEPTED.into_response(),
+        },
+    }
+}
+
+/// `GET /mcp` — flux SSE des messages initiés par le serveur.
+///
+/// Avec `Last-Event-ID`, le flux de cet événement (autonome ou réponse POST)
+/// reprend depuis le journal.
+pub async fn mcp_get(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Arc<Principal>>>,
+    headers: HeaderMap,
+) -> Response {
+    if !accepts_event_stream(&headers) {
+        return (StatusCode::METHOD_NOT_ALLOWED, "GET /mcp requires Accept: text/event-stream")
+            .into_response();
+    }
+    let Some(id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response();
+    };
+    let owner = principal.as_ref().map(|Extension(p)| p.name.as_str());
+    let Some(session) = state.mcp_sessions.get(id, owner) else {
+        return session_not_found();
+    };
+
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let stream = last_event_id
+        .and_then(|event_id| session.stream_of(event_id))
+        .unwrap_or_else(|| STANDALONE_STREAM.into());
+
+    let events = session.subscribe(stream, last_event_id);
+    Sse::new(events.into_sse())
+        .keep_alive(KeepAlive::new())
+        .into_response()
+}
+
+/// `DELETE /mcp` — termine explicitement la session.
+pub async fn mcp_delete(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Arc<Principal>>>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response();
+    };
+    let owner = principal.as_ref().map(|Extension(p)| p.name.as_str());
+    match state.mcp_sessions.remove(id, owner) {
+        Some(_) => {
+            end_session(&state, id);
+            info!(session = %id, "Streamable HTTP session terminated");
+            StatusCode::OK.into_response()
+        }
+        None => session_not_found(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn ids(events: &VecDeque<StoredEvent>) -> Vec<u64> {
+        events.iter().map(|e| e.id).collect()
+    }
+
+    #[tokio::test]
+    async fn resumes_a_stream_after_the_last_event_id() {
+        let session = McpSession::new(None, 1024 * 1024);
+        session.push(STANDALONE_STREAM, Some("a".into()), false);
+        session.push("post", Some("progress".into()), false);
+        session.push(STANDALONE_STREAM, Some("b".into()), false);
+        session.push("post", Some("result".into()), true);
+
+        assert_eq!(session.stream_of(2).as_deref(), Some("post"));
+        assert_eq!(session.stream_of(3).as_deref(), Some(STANDALONE_STREAM));
+        assert_eq!(session.stream_of(99), None);
+
+        assert_eq!(ids(&session.subscribe("post".into(), Some(2)).replay), [4]);
+        assert_eq!(ids(&session.subscribe(STANDALONE_STREAM.into(), Some(1)).replay), [3]);
+        // Sans `Last-Event-ID`, rien n'est rejoué.
+        assert!(session.subscribe(STANDALONE_STREAM.into(), None).replay.is_empty());
+        // Repris après sa réponse, un flux POST est terminé.
+        assert!(session.subscribe("post".into(), Some(4)).done);
+
+        // Le flux rejoué s'arrête après le dernier événement de la réponse.
+        let events = session.subscribe("post".into(), Some(0)).into_sse();
+        assert_eq!(events.collect::<Vec<_>>().await.len(), 2);
+    }
+
+    #[test]
+    fn caps_the_replay_log_by_bytes() {
+        // `standalone` (10 octets) et 5 octets de message : 15 par événement.
+        let session = McpSession::new(None, 40);
+        for _ in 0..3 {
+            session.push(STANDALONE_STREAM, Some("hello".into()), false);
+        }
+        {
+            let log = session.log.lock().unwrap();
+            assert_eq!(ids(&log.events), [2, 3]);
+            assert_eq!(log.bytes, 30);
+        }
+        assert_eq!(session.stream_of(1), None);
+
+        // Un événement plus gros que la limite reste seul dans le journal.
+        session.push(STANDALONE_STREAM, Some("x".repeat(100)), false);
+        let log = session.log.lock().unwrap();
+        assert_eq!(ids(&log.events), [4]);
+        assert_eq!(log.bytes, 110);
+    }
+
+    #[test]
+    fn refuses_unknown_and_foreign_session_ids() {
+        let sessions = McpSessions::new(10, 1024);
+        let id = sessions.create(Some("alice".into())).unwrap().id.clone();
+
+        assert!(sessions.get(&id, Some("alice")).is_some());
+        assert!(sessions.get("unknown", Some("alice")).is_none());
+        assert!(sessions.get(&id, Some("bob")).is_none());
+        assert!(sessions.get(&id, None).is_none());
+        assert!(sessions.remove(&id, Some("bob")).is_none());
+        assert!(sessions.remove(&id, Some("alice")).is_some());
+        assert!(sessions.get(&id, Some("alice")).is_none());
+        assert!(sessions.remove(&id, Some("alice")).is_none());
+    }
+
+    #[test]
+    fn expires_idle_sessions_without_an_open_stream() {
+        let mut sessions = McpSessions::new(10, 1024);
+        sessions.idle_ttl = Duration::ZERO;
+        let session = sessions.create(None).unwrap();
+
+        // Un flux ouvert garde la session en vie.
+        let events = session.subscribe(STANDALONE_STREAM.into(), None);
+        assert!(sessions.get(&session.id, None).is_some());
+        assert!(sessions.sweep().is_empty());
+
+        drop(events);
+        assert!(sessions.get(&session.id, None).is_none());
+        assert_eq!(sessions.sweep(), [session.id.as_str()]);
+        assert!(sessions.remove(&session.id, None).is_none());
+    }
+
+    #[test]
+    fn limits_the_number_of_sessions() {
+        let sessions = McpSessions::new(2, 1024);
+        let first = sessions.create(None).unwrap();
+        assert!(sessions.create(None).is_some());
+        assert!(sessions.create(None).is_none());
+
+        sessions.remove(&first.id, None);
+        assert!(sessions.create(None).is_some());
+    }
+}